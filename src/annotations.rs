@@ -0,0 +1,382 @@
+use crate::vcl::{
+    BackendSettings, BasicAuth, CacheSettings, MatchCondition, MatchConditions, Probe, Redirect,
+    validate_backend_settings, validate_conditions, validate_duration, validate_probe, vcl_string,
+};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+pub const TTL_ANNOTATION: &str = "vingress.io/ttl";
+pub const GRACE_ANNOTATION: &str = "vingress.io/grace";
+pub const KEEP_ANNOTATION: &str = "vingress.io/keep";
+pub const BYPASS_ANNOTATION: &str = "vingress.io/bypass";
+pub const BYPASS_PATHS_ANNOTATION: &str = "vingress.io/bypass-paths";
+pub const PROBE_URL_ANNOTATION: &str = "vingress.io/probe-url";
+pub const PROBE_INTERVAL_ANNOTATION: &str = "vingress.io/probe-interval";
+pub const PROBE_TIMEOUT_ANNOTATION: &str = "vingress.io/probe-timeout";
+pub const PROBE_WINDOW_ANNOTATION: &str = "vingress.io/probe-window";
+pub const PROBE_THRESHOLD_ANNOTATION: &str = "vingress.io/probe-threshold";
+pub const CONNECT_TIMEOUT_ANNOTATION: &str = "vingress.io/connect-timeout";
+pub const FIRST_BYTE_TIMEOUT_ANNOTATION: &str = "vingress.io/first-byte-timeout";
+pub const BETWEEN_BYTES_TIMEOUT_ANNOTATION: &str = "vingress.io/between-bytes-timeout";
+pub const MAX_CONNECTIONS_ANNOTATION: &str = "vingress.io/max-connections";
+pub const HOST_HEADER_ANNOTATION: &str = "vingress.io/host-header";
+pub const CANARY_ANNOTATION: &str = "vingress.io/canary";
+pub const CANARY_WEIGHT_ANNOTATION: &str = "vingress.io/canary-weight";
+pub const MATCH_HEADER_ANNOTATION: &str = "vingress.io/match-header";
+pub const MATCH_COOKIE_ANNOTATION: &str = "vingress.io/match-cookie";
+pub const REWRITE_TARGET_ANNOTATION: &str = "vingress.io/rewrite-target";
+pub const SSL_REDIRECT_ANNOTATION: &str = "vingress.io/ssl-redirect";
+pub const PERMANENT_REDIRECT_ANNOTATION: &str = "vingress.io/permanent-redirect";
+pub const TEMPORARY_REDIRECT_ANNOTATION: &str = "vingress.io/temporary-redirect";
+pub const WWW_REDIRECT_ANNOTATION: &str = "vingress.io/from-to-www-redirect";
+pub const AUTH_SECRET_ANNOTATION: &str = "vingress.io/auth-secret";
+pub const AUTH_REALM_ANNOTATION: &str = "vingress.io/auth-realm";
+
+/// The realm advertised when the auth-realm annotation is not set.
+const DEFAULT_AUTH_REALM: &str = "Authentication Required";
+
+/// The vcl_recv return actions allowed by the bypass annotation.
+const BYPASS_MODES: [&str; 2] = ["pass", "pipe"];
+
+///
+/// Bypass sends the requests of an Ingress, or of some of its
+/// paths, straight to the backend with either `return (pass)`
+/// or `return (pipe)` in vcl_recv.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bypass {
+    /// Either pass or pipe.
+    pub mode: String,
+
+    /// The Ingress rule paths the bypass applies to,
+    /// all of them when empty.
+    pub paths: Vec<String>,
+}
+
+impl Bypass {
+    /// The bypass mode of the route with the provided path, if any.
+    pub fn mode_for(&self, path: &str) -> Option<String> {
+        (self.paths.is_empty() || self.paths.iter().any(|p| p == path)).then(|| self.mode.clone())
+    }
+}
+
+///
+/// Read the value of an Ingress annotation, surrounding
+/// whitespace is ignored and empty values are discarded.
+///
+fn annotation<'a>(metadata: &'a ObjectMeta, key: &str) -> Option<&'a str> {
+    metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(key))
+        .map(|value| value.trim())
+        .filter(|value| !value.is_empty())
+}
+
+fn duration_annotation(metadata: &ObjectMeta, key: &str) -> Result<Option<String>, String> {
+    annotation(metadata, key)
+        .map(|value| {
+            validate_duration(value)
+                .map(|_| value.to_string())
+                .map_err(|e| format!("Annotation [{key}]: {e}"))
+        })
+        .transpose()
+}
+
+fn bool_annotation(metadata: &ObjectMeta, key: &str) -> Result<Option<bool>, String> {
+    annotation(metadata, key)
+        .map(|value| match value {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            _ => Err(format!(
+                "Annotation [{key}]: invalid value [{}], expected true or false",
+                value.escape_debug()
+            )),
+        })
+        .transpose()
+}
+
+fn number_annotation(metadata: &ObjectMeta, key: &str) -> Result<Option<u32>, String> {
+    annotation(metadata, key)
+        .map(|value| {
+            value.parse::<u32>().map_err(|e| {
+                format!(
+                    "Annotation [{key}]: invalid number [{}]: {e}",
+                    value.escape_debug()
+                )
+            })
+        })
+        .transpose()
+}
+
+///
+/// Parse the vingress.io/ttl, vingress.io/grace and
+/// vingress.io/keep annotations of an Ingress.
+///
+pub fn parse_cache_settings(metadata: &ObjectMeta) -> Result<CacheSettings, String> {
+    Ok(CacheSettings {
+        ttl: duration_annotation(metadata, TTL_ANNOTATION)?,
+        grace: duration_annotation(metadata, GRACE_ANNOTATION)?,
+        keep: duration_annotation(metadata, KEEP_ANNOTATION)?,
+    })
+}
+
+///
+/// Parse the vingress.io/bypass and vingress.io/bypass-paths
+/// annotations of an Ingress.
+///
+/// The paths are a comma separated list of the Ingress rule paths,
+/// they are rejected when set without the bypass mode.
+///
+pub fn parse_bypass(metadata: &ObjectMeta) -> Result<Option<Bypass>, String> {
+    let paths: Vec<String> = annotation(metadata, BYPASS_PATHS_ANNOTATION)
+        .unwrap_or_default()
+        .split(',')
+        .map(|path| path.trim())
+        .filter(|path| !path.is_empty())
+        .map(|path| path.to_string())
+        .collect();
+
+    let Some(mode) = annotation(metadata, BYPASS_ANNOTATION) else {
+        if !paths.is_empty() {
+            return Err(format!(
+                "Annotation [{BYPASS_PATHS_ANNOTATION}] requires [{BYPASS_ANNOTATION}]"
+            ));
+        }
+        return Ok(None);
+    };
+
+    if !BYPASS_MODES.contains(&mode) {
+        return Err(format!(
+            "Annotation [{BYPASS_ANNOTATION}]: invalid mode [{}], expected one of {BYPASS_MODES:?}",
+            mode.escape_debug()
+        ));
+    }
+
+    Ok(Some(Bypass {
+        mode: mode.to_string(),
+        paths,
+    }))
+}
+
+///
+/// Parse the vingress.io/probe-* annotations of an Ingress,
+/// any of them enables a health probe on the Ingress backends.
+///
+pub fn parse_probe(metadata: &ObjectMeta) -> Result<Option<Probe>, String> {
+    let probe = Probe {
+        url: annotation(metadata, PROBE_URL_ANNOTATION).map(|url| url.to_string()),
+        interval: duration_annotation(metadata, PROBE_INTERVAL_ANNOTATION)?,
+        timeout: duration_annotation(metadata, PROBE_TIMEOUT_ANNOTATION)?,
+        window: number_annotation(metadata, PROBE_WINDOW_ANNOTATION)?,
+        threshold: number_annotation(metadata, PROBE_THRESHOLD_ANNOTATION)?,
+    };
+
+    if probe == Probe::default() {
+        return Ok(None);
+    }
+
+    validate_probe(&probe).map_err(|e| format!("Probe annotations: {e}"))?;
+    Ok(Some(probe))
+}
+
+///
+/// Parse the connection and timeout annotations of an Ingress.
+///
+pub fn parse_backend_settings(metadata: &ObjectMeta) -> Result<BackendSettings, String> {
+    let settings = BackendSettings {
+        connect_timeout: duration_annotation(metadata, CONNECT_TIMEOUT_ANNOTATION)?,
+        first_byte_timeout: duration_annotation(metadata, FIRST_BYTE_TIMEOUT_ANNOTATION)?,
+        between_bytes_timeout: duration_annotation(metadata, BETWEEN_BYTES_TIMEOUT_ANNOTATION)?,
+        max_connections: number_annotation(metadata, MAX_CONNECTIONS_ANNOTATION)?,
+        host_header: annotation(metadata, HOST_HEADER_ANNOTATION).map(|host| host.to_string()),
+    };
+
+    validate_backend_settings(&settings)
+        .map_err(|e| format!("Annotation [{HOST_HEADER_ANNOTATION}]: {e}"))?;
+
+    Ok(settings)
+}
+
+///
+/// Parse the vingress.io/canary and vingress.io/canary-weight
+/// annotations of an Ingress.
+///
+/// Returns the percentage of the traffic sent to the routes of a
+/// canary Ingress, 0 when the weight is not set, none otherwise.
+///
+pub fn parse_canary(metadata: &ObjectMeta) -> Result<Option<u32>, String> {
+    let weight = number_annotation(metadata, CANARY_WEIGHT_ANNOTATION)?;
+
+    if !bool_annotation(metadata, CANARY_ANNOTATION)?.unwrap_or_default() {
+        if weight.is_some() {
+            return Err(format!(
+                "Annotation [{CANARY_WEIGHT_ANNOTATION}] requires [{CANARY_ANNOTATION}]"
+            ));
+        }
+        return Ok(None);
+    }
+
+    let weight = weight.unwrap_or_default();
+    if weight > 100 {
+        return Err(format!(
+            "Annotation [{CANARY_WEIGHT_ANNOTATION}]: weight [{weight}] must be between 0 and 100"
+        ));
+    }
+
+    Ok(Some(weight))
+}
+
+///
+/// Parse the vingress.io/match-header annotation, e.g. `X-Beta: true`,
+/// and the vingress.io/match-cookie annotation, e.g. `beta=true`.
+///
+pub fn parse_match_conditions(metadata: &ObjectMeta) -> Result<MatchConditions, String> {
+    let condition = |key: &str, separator: char| {
+        annotation(metadata, key)
+            .map(|value| {
+                value
+                    .split_once(separator)
+                    .map(|(name, value)| MatchCondition {
+                        name: name.trim().to_string(),
+                        value: value.trim().to_string(),
+                    })
+                    .ok_or(format!(
+                        "Annotation [{key}]: [{}] is missing the '{separator}' separator",
+                        value.escape_debug()
+                    ))
+            })
+            .transpose()
+    };
+
+    let conditions = MatchConditions {
+        header: condition(MATCH_HEADER_ANNOTATION, ':')?,
+        cookie: condition(MATCH_COOKIE_ANNOTATION, '=')?,
+    };
+
+    validate_conditions(&conditions).map_err(|e| format!("Match annotations: {e}"))?;
+    Ok(conditions)
+}
+
+///
+/// Parse the vingress.io/rewrite-target annotation of an Ingress,
+/// e.g. `/$1`, where `$1` to `$9` reference the capture groups
+/// of the Ingress rule path.
+///
+pub fn parse_rewrite_target(metadata: &ObjectMeta) -> Result<Option<String>, String> {
+    let Some(target) = annotation(metadata, REWRITE_TARGET_ANNOTATION) else {
+        return Ok(None);
+    };
+
+    if !target.starts_with('/') || target.contains('\\') {
+        return Err(format!(
+            "Annotation [{REWRITE_TARGET_ANNOTATION}]: target [{}] must start with a '/' \
+             and must not contain a '\\'",
+            target.escape_debug()
+        ));
+    }
+
+    vcl_string(target).map_err(|e| format!("Annotation [{REWRITE_TARGET_ANNOTATION}]: {e}"))?;
+    Ok(Some(target.to_string()))
+}
+
+///
+/// Parse the vingress.io/ssl-redirect annotation of an Ingress,
+/// the TLS hosts are redirected to HTTPS unless it is false.
+///
+pub fn parse_ssl_redirect(metadata: &ObjectMeta) -> Result<bool, String> {
+    Ok(bool_annotation(metadata, SSL_REDIRECT_ANNOTATION)?.unwrap_or(true))
+}
+
+///
+/// Parse the vingress.io/from-to-www-redirect annotation of an Ingress.
+///
+pub fn parse_www_redirect(metadata: &ObjectMeta) -> Result<bool, String> {
+    Ok(bool_annotation(metadata, WWW_REDIRECT_ANNOTATION)?.unwrap_or_default())
+}
+
+///
+/// Parse the vingress.io/permanent-redirect and
+/// vingress.io/temporary-redirect annotations of an Ingress,
+/// which are mutually exclusive.
+///
+/// The target is either an absolute http(s) URL or a path.
+///
+pub fn parse_redirect(metadata: &ObjectMeta) -> Result<Option<Redirect>, String> {
+    let permanent = annotation(metadata, PERMANENT_REDIRECT_ANNOTATION);
+    let temporary = annotation(metadata, TEMPORARY_REDIRECT_ANNOTATION);
+
+    let (key, code, location) = match (permanent, temporary) {
+        (Some(_), Some(_)) => {
+            return Err(format!(
+                "Annotations [{PERMANENT_REDIRECT_ANNOTATION}] and \
+                 [{TEMPORARY_REDIRECT_ANNOTATION}] are mutually exclusive"
+            ));
+        }
+        (Some(location), None) => (PERMANENT_REDIRECT_ANNOTATION, 301, location),
+        (None, Some(location)) => (TEMPORARY_REDIRECT_ANNOTATION, 302, location),
+        (None, None) => return Ok(None),
+    };
+
+    if !["http://", "https://", "/"]
+        .iter()
+        .any(|prefix| location.starts_with(prefix))
+    {
+        return Err(format!(
+            "Annotation [{key}]: target [{}] must be an http(s) URL or a path",
+            location.escape_debug()
+        ));
+    }
+
+    vcl_string(location).map_err(|e| format!("Annotation [{key}]: {e}"))?;
+
+    Ok(Some(Redirect {
+        code,
+        location: location.to_string(),
+    }))
+}
+
+///
+/// Parse the vingress.io/auth-secret annotation of an Ingress, naming
+/// the basic auth Secret in its namespace, and the optional realm
+/// set by the vingress.io/auth-realm annotation.
+///
+/// The credentials are filled in from the Secret afterwards.
+///
+pub fn parse_auth(metadata: &ObjectMeta) -> Result<Option<BasicAuth>, String> {
+    let realm = annotation(metadata, AUTH_REALM_ANNOTATION);
+
+    let Some(secret) = annotation(metadata, AUTH_SECRET_ANNOTATION) else {
+        if realm.is_some() {
+            return Err(format!(
+                "Annotation [{AUTH_REALM_ANNOTATION}] requires [{AUTH_SECRET_ANNOTATION}]"
+            ));
+        }
+        return Ok(None);
+    };
+
+    if !secret
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '.')
+    {
+        return Err(format!(
+            "Annotation [{AUTH_SECRET_ANNOTATION}]: [{}] is not a Secret name",
+            secret.escape_debug()
+        ));
+    }
+
+    let realm = realm.unwrap_or(DEFAULT_AUTH_REALM);
+    if realm.contains(['"', '\\']) {
+        return Err(format!(
+            "Annotation [{AUTH_REALM_ANNOTATION}]: realm [{}] must not contain a '\"' or a '\\'",
+            realm.escape_debug()
+        ));
+    }
+    vcl_string(realm).map_err(|e| format!("Annotation [{AUTH_REALM_ANNOTATION}]: {e}"))?;
+
+    Ok(Some(BasicAuth {
+        secret: secret.to_string(),
+        realm: realm.to_string(),
+        credentials: vec![],
+        sha1: vec![],
+    }))
+}
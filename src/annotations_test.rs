@@ -0,0 +1,279 @@
+#[cfg(test)]
+mod test {
+    use crate::annotations::{
+        AUTH_REALM_ANNOTATION, AUTH_SECRET_ANNOTATION, BYPASS_ANNOTATION, BYPASS_PATHS_ANNOTATION,
+        CANARY_ANNOTATION, CANARY_WEIGHT_ANNOTATION, CONNECT_TIMEOUT_ANNOTATION, GRACE_ANNOTATION,
+        HOST_HEADER_ANNOTATION, KEEP_ANNOTATION, MATCH_COOKIE_ANNOTATION, MATCH_HEADER_ANNOTATION,
+        MAX_CONNECTIONS_ANNOTATION, PERMANENT_REDIRECT_ANNOTATION, PROBE_INTERVAL_ANNOTATION,
+        PROBE_THRESHOLD_ANNOTATION, PROBE_URL_ANNOTATION, PROBE_WINDOW_ANNOTATION,
+        REWRITE_TARGET_ANNOTATION, SSL_REDIRECT_ANNOTATION, TEMPORARY_REDIRECT_ANNOTATION,
+        TTL_ANNOTATION, WWW_REDIRECT_ANNOTATION, parse_auth, parse_backend_settings, parse_bypass,
+        parse_cache_settings, parse_canary, parse_match_conditions, parse_probe, parse_redirect,
+        parse_rewrite_target, parse_ssl_redirect, parse_www_redirect,
+    };
+    use crate::vcl::Redirect;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use std::collections::BTreeMap;
+
+    fn metadata(annotations: &[(&str, &str)]) -> ObjectMeta {
+        ObjectMeta {
+            annotations: Some(
+                annotations
+                    .iter()
+                    .map(|(k, v)| (k.to_string(), v.to_string()))
+                    .collect::<BTreeMap<String, String>>(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_parse_cache_settings() {
+        let settings = parse_cache_settings(&metadata(&[
+            (TTL_ANNOTATION, "5m"),
+            (GRACE_ANNOTATION, " 1h "),
+            (KEEP_ANNOTATION, ""),
+        ]))
+        .unwrap();
+
+        assert_eq!(settings.ttl.as_deref(), Some("5m"));
+        assert_eq!(settings.grace.as_deref(), Some("1h"));
+        assert_eq!(settings.keep, None);
+
+        assert!(
+            parse_cache_settings(&ObjectMeta::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        let err = parse_cache_settings(&metadata(&[(TTL_ANNOTATION, "5 minutes")])).unwrap_err();
+        assert!(err.contains(TTL_ANNOTATION));
+    }
+
+    #[test]
+    fn test_parse_bypass() {
+        assert_eq!(parse_bypass(&ObjectMeta::default()).unwrap(), None);
+
+        let bypass = parse_bypass(&metadata(&[(BYPASS_ANNOTATION, "pass")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(bypass.mode_for("/api").as_deref(), Some("pass"));
+        assert_eq!(bypass.mode_for("/").as_deref(), Some("pass"));
+
+        let bypass = parse_bypass(&metadata(&[
+            (BYPASS_ANNOTATION, "pipe"),
+            (BYPASS_PATHS_ANNOTATION, "/stream, /events"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(bypass.paths, vec!["/stream", "/events"]);
+        assert_eq!(bypass.mode_for("/events").as_deref(), Some("pipe"));
+        assert_eq!(bypass.mode_for("/"), None);
+
+        assert!(parse_bypass(&metadata(&[(BYPASS_ANNOTATION, "hash")])).is_err());
+        assert!(parse_bypass(&metadata(&[(BYPASS_PATHS_ANNOTATION, "/api")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_probe() {
+        assert_eq!(parse_probe(&ObjectMeta::default()).unwrap(), None);
+
+        let probe = parse_probe(&metadata(&[
+            (PROBE_URL_ANNOTATION, "/healthz"),
+            (PROBE_INTERVAL_ANNOTATION, "2s"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(probe.url.as_deref(), Some("/healthz"));
+        assert_eq!(probe.interval.as_deref(), Some("2s"));
+        assert_eq!(probe.timeout, None);
+
+        assert!(parse_probe(&metadata(&[(PROBE_WINDOW_ANNOTATION, "five")])).is_err());
+        assert!(
+            parse_probe(&metadata(&[
+                (PROBE_WINDOW_ANNOTATION, "3"),
+                (PROBE_THRESHOLD_ANNOTATION, "4"),
+            ]))
+            .is_err()
+        );
+        assert!(parse_probe(&metadata(&[(PROBE_URL_ANNOTATION, "/\"}")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_backend_settings() {
+        assert!(
+            parse_backend_settings(&ObjectMeta::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        let settings = parse_backend_settings(&metadata(&[
+            (CONNECT_TIMEOUT_ANNOTATION, "500ms"),
+            (MAX_CONNECTIONS_ANNOTATION, "100"),
+            (HOST_HEADER_ANNOTATION, "api.foo.com:8080"),
+        ]))
+        .unwrap();
+        assert_eq!(settings.connect_timeout.as_deref(), Some("500ms"));
+        assert_eq!(settings.max_connections, Some(100));
+        assert_eq!(settings.host_header.as_deref(), Some("api.foo.com:8080"));
+
+        assert!(
+            parse_backend_settings(&metadata(&[(CONNECT_TIMEOUT_ANNOTATION, "fast")])).is_err()
+        );
+        assert!(parse_backend_settings(&metadata(&[(MAX_CONNECTIONS_ANNOTATION, "-1")])).is_err());
+        assert!(
+            parse_backend_settings(&metadata(&[(HOST_HEADER_ANNOTATION, "foo\"; }")])).is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_canary() {
+        assert_eq!(parse_canary(&ObjectMeta::default()), Ok(None));
+        assert_eq!(
+            parse_canary(&metadata(&[(CANARY_ANNOTATION, "false")])),
+            Ok(None)
+        );
+        assert_eq!(
+            parse_canary(&metadata(&[(CANARY_ANNOTATION, "true")])),
+            Ok(Some(0))
+        );
+        assert_eq!(
+            parse_canary(&metadata(&[
+                (CANARY_ANNOTATION, "true"),
+                (CANARY_WEIGHT_ANNOTATION, "20")
+            ])),
+            Ok(Some(20))
+        );
+
+        assert!(parse_canary(&metadata(&[(CANARY_ANNOTATION, "yes")])).is_err());
+        assert!(parse_canary(&metadata(&[(CANARY_WEIGHT_ANNOTATION, "20")])).is_err());
+        assert!(
+            parse_canary(&metadata(&[
+                (CANARY_ANNOTATION, "true"),
+                (CANARY_WEIGHT_ANNOTATION, "101")
+            ]))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_match_conditions() {
+        assert!(
+            parse_match_conditions(&ObjectMeta::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        let conditions = parse_match_conditions(&metadata(&[
+            (MATCH_HEADER_ANNOTATION, "X-Beta: true"),
+            (MATCH_COOKIE_ANNOTATION, "beta=1"),
+        ]))
+        .unwrap();
+        let header = conditions.header.unwrap();
+        assert_eq!(
+            (header.name.as_str(), header.value.as_str()),
+            ("X-Beta", "true")
+        );
+        let cookie = conditions.cookie.unwrap();
+        assert_eq!((cookie.name.as_str(), cookie.value.as_str()), ("beta", "1"));
+
+        assert!(parse_match_conditions(&metadata(&[(MATCH_HEADER_ANNOTATION, "X-Beta")])).is_err());
+        assert!(
+            parse_match_conditions(&metadata(&[(MATCH_HEADER_ANNOTATION, "X Beta: true")]))
+                .is_err()
+        );
+        assert!(
+            parse_match_conditions(&metadata(&[(MATCH_HEADER_ANNOTATION, "X-Beta: \"}")])).is_err()
+        );
+        assert!(parse_match_conditions(&metadata(&[(MATCH_COOKIE_ANNOTATION, "=1")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_rewrite_target() {
+        assert_eq!(parse_rewrite_target(&ObjectMeta::default()), Ok(None));
+        assert_eq!(
+            parse_rewrite_target(&metadata(&[(REWRITE_TARGET_ANNOTATION, " /$1 ")])),
+            Ok(Some(String::from("/$1")))
+        );
+        assert!(parse_rewrite_target(&metadata(&[(REWRITE_TARGET_ANNOTATION, "users")])).is_err());
+        assert!(parse_rewrite_target(&metadata(&[(REWRITE_TARGET_ANNOTATION, "/\\1")])).is_err());
+        assert!(parse_rewrite_target(&metadata(&[(REWRITE_TARGET_ANNOTATION, "/\"}")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_redirects() {
+        assert_eq!(parse_ssl_redirect(&ObjectMeta::default()), Ok(true));
+        assert_eq!(
+            parse_ssl_redirect(&metadata(&[(SSL_REDIRECT_ANNOTATION, "false")])),
+            Ok(false)
+        );
+        assert!(parse_ssl_redirect(&metadata(&[(SSL_REDIRECT_ANNOTATION, "no")])).is_err());
+
+        assert_eq!(parse_www_redirect(&ObjectMeta::default()), Ok(false));
+        assert_eq!(
+            parse_www_redirect(&metadata(&[(WWW_REDIRECT_ANNOTATION, "true")])),
+            Ok(true)
+        );
+
+        assert_eq!(parse_redirect(&ObjectMeta::default()), Ok(None));
+        assert_eq!(
+            parse_redirect(&metadata(&[(
+                PERMANENT_REDIRECT_ANNOTATION,
+                "https://bar.com/"
+            )])),
+            Ok(Some(Redirect {
+                code: 301,
+                location: String::from("https://bar.com/"),
+            }))
+        );
+        assert_eq!(
+            parse_redirect(&metadata(&[(
+                TEMPORARY_REDIRECT_ANNOTATION,
+                "/maintenance"
+            )])),
+            Ok(Some(Redirect {
+                code: 302,
+                location: String::from("/maintenance"),
+            }))
+        );
+        assert!(
+            parse_redirect(&metadata(&[
+                (PERMANENT_REDIRECT_ANNOTATION, "/a"),
+                (TEMPORARY_REDIRECT_ANNOTATION, "/b"),
+            ]))
+            .is_err()
+        );
+        assert!(parse_redirect(&metadata(&[(PERMANENT_REDIRECT_ANNOTATION, "bar.com")])).is_err());
+        assert!(parse_redirect(&metadata(&[(PERMANENT_REDIRECT_ANNOTATION, "/\"}")])).is_err());
+    }
+
+    #[test]
+    fn test_parse_auth() {
+        assert_eq!(parse_auth(&ObjectMeta::default()), Ok(None));
+
+        let auth = parse_auth(&metadata(&[(AUTH_SECRET_ANNOTATION, "basic-auth")]))
+            .unwrap()
+            .unwrap();
+        assert_eq!(auth.secret, "basic-auth");
+        assert_eq!(auth.realm, "Authentication Required");
+        assert!(auth.credentials.is_empty());
+
+        let auth = parse_auth(&metadata(&[
+            (AUTH_SECRET_ANNOTATION, "basic-auth"),
+            (AUTH_REALM_ANNOTATION, "Admin area"),
+        ]))
+        .unwrap()
+        .unwrap();
+        assert_eq!(auth.realm, "Admin area");
+
+        assert!(parse_auth(&metadata(&[(AUTH_REALM_ANNOTATION, "Admin area")])).is_err());
+        assert!(parse_auth(&metadata(&[(AUTH_SECRET_ANNOTATION, "other/basic-auth")])).is_err());
+        assert!(
+            parse_auth(&metadata(&[
+                (AUTH_SECRET_ANNOTATION, "basic-auth"),
+                (AUTH_REALM_ANNOTATION, "\"}"),
+            ]))
+            .is_err()
+        );
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{ArgAction, Parser};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -28,11 +28,31 @@ pub struct Args {
 
     #[arg(
         long,
+        env = "INGRESS_CLASS",
         default_value = "varnish",
-        help = "Sets the ingress class that controller will be looking for"
+        help = "Sets the ingress class matched against the legacy \
+             [kubernetes.io/ingress.class] annotation"
     )]
     pub ingress_class: String,
 
+    #[arg(
+        long,
+        env = "INGRESS_LABEL_FILTER",
+        default_value_t = false,
+        action = ArgAction::Set,
+        help = "Watches only the Ingresses labeled with [kubernetes.io/ingress=<ingress-class>]"
+    )]
+    pub ingress_label_filter: bool,
+
+    #[arg(
+        long,
+        env = "CONTROLLER_NAME",
+        default_value = "varnish.io/varnish-ingress-controller",
+        help = "Sets the controller name, the controller claims the IngressClasses \
+             whose [spec.controller] matches it"
+    )]
+    pub controller_name: String,
+
     #[arg(
         long,
         default_value = "/etc/varnish",
@@ -90,6 +110,80 @@ pub struct Args {
     )]
     pub vcl_recv_snippet: String,
 
+    #[arg(
+        long,
+        env = "VARNISH_DEFAULT_BACKEND",
+        default_value = "",
+        help = "Fallback backend for requests which match no Ingress, \
+             in the <namespace>/<service>:<port> notation"
+    )]
+    pub default_backend: String,
+
+    #[arg(
+        long,
+        env = "VARNISH_NORMALIZE_HOST",
+        default_value_t = true,
+        action = ArgAction::Set,
+        help = "Normalizes the Host header before routing: lowercase it, \
+             strip the default or listen port and the trailing dot"
+    )]
+    pub normalize_host: bool,
+
+    #[arg(
+        long,
+        env = "VARNISH_DEFAULT_PROBE",
+        default_value = "",
+        help = "Health probe of the backends without a probe annotation, \
+             e.g. url=/healthz,interval=5s,timeout=1s,window=5,threshold=3"
+    )]
+    pub default_probe: String,
+
+    #[arg(
+        long,
+        env = "VARNISH_ENDPOINT_BACKENDS",
+        default_value_t = false,
+        action = ArgAction::Set,
+        help = "Renders one backend per ready endpoint of the services, \
+             grouped under a director, instead of one backend per service"
+    )]
+    pub endpoint_backends: bool,
+
+    #[arg(
+        long,
+        env = "VARNISH_DIRECTOR",
+        default_value = "round_robin",
+        value_parser = ["round_robin", "random", "fallback"],
+        help = "The vmod_directors director balancing the endpoint backends"
+    )]
+    pub director: String,
+
+    #[arg(
+        long,
+        env = "VARNISH_DIGEST_VMOD",
+        default_value_t = false,
+        action = ArgAction::Set,
+        help = "Imports the digest vmod, which verifies the {SHA} htpasswd entries \
+             of the basic auth Secrets"
+    )]
+    pub digest_vmod: bool,
+
+    #[arg(
+        long,
+        env = "WATCH_NAMESPACE",
+        value_delimiter = ',',
+        help = "Restricts the controller to the given namespace, can be repeated. \
+             All the namespaces are watched when not set"
+    )]
+    pub watch_namespace: Vec<String>,
+
+    #[arg(
+        long,
+        env = "NAMESPACE_SELECTOR",
+        default_value = "",
+        help = "Restricts the controller to the namespaces matching the label selector"
+    )]
+    pub namespace_selector: String,
+
     #[arg(
         long,
         env = "NAMESPACE",
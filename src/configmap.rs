@@ -2,26 +2,30 @@ use futures::{StreamExt, TryStreamExt};
 use k8s_openapi::{Metadata, api::core::v1::ConfigMap};
 use kube::runtime::watcher::Error as WatcherError;
 use kube::{
-    Api, Client,
+    Api, Client, Resource,
     runtime::{WatchStreamExt, watcher},
 };
 use log::{error, info, warn};
 use std::process;
 use std::{cell::RefCell, rc::Rc};
 
-use crate::vcl::{Vcl, reload, update};
+use crate::events::Events;
+use crate::vcl::{Vcl, parse_probe, reload, update};
 
 const CONFIGMAP_NAME: &str = "varnish-vcl";
 
 pub const SNIPPET_KEY: &str = "snippet";
 pub const VCL_RECV_SNIPPET_KEY: &str = "vcl_recv_snippet";
+pub const DEFAULT_PROBE_KEY: &str = "default_probe";
 
 pub async fn watch_configmap(
     client: Client,
     vcl: &Rc<RefCell<Vcl<'_>>>,
     namespace: &str,
+    controller_name: &str,
 ) -> Result<(), WatcherError> {
-    let configmap_api: Api<ConfigMap> = Api::namespaced(client, namespace);
+    let configmap_api: Api<ConfigMap> = Api::namespaced(client.clone(), namespace);
+    let mut events = Events::new(client, controller_name);
 
     let mut observer = watcher(configmap_api, watcher::Config::default())
         .default_backoff()
@@ -32,17 +36,39 @@ pub async fn watch_configmap(
     );
 
     while let Some(event) = observer.try_next().await.unwrap() {
-        match event {
-            watcher::Event::Apply(cm) => handle_configmap_event(&cm, vcl, CONFIGMAP_NAME),
-            watcher::Event::Delete(cm) => handle_configmap_event(&cm, vcl, CONFIGMAP_NAME),
-            _ => {}
+        let result = match event {
+            watcher::Event::Apply(cm) => {
+                handle_configmap_event(&cm, vcl, CONFIGMAP_NAME, &mut events)
+            }
+            watcher::Event::Delete(cm) => {
+                handle_configmap_event(&cm, vcl, CONFIGMAP_NAME, &mut events)
+            }
+            _ => Ok(()),
+        };
+
+        events.flush().await;
+
+        if let Err(e) = result {
+            error!("{e}");
+            process::exit(1);
         }
     }
 
     Ok(())
 }
 
-fn handle_configmap_event(cm: &ConfigMap, vcl: &Rc<RefCell<Vcl>>, configmap_name: &str) {
+///
+/// Render the snippets of the configmap into the VCL and reload it,
+/// the outcome is recorded as an Event against the configmap.
+///
+/// Returns an error when the VCL could not be reloaded.
+///
+fn handle_configmap_event(
+    cm: &ConfigMap,
+    vcl: &Rc<RefCell<Vcl>>,
+    configmap_name: &str,
+    events: &mut Events,
+) -> Result<(), String> {
     match cm.metadata().name.as_deref() {
         Some(name) if name == configmap_name => {
             info!("Reading the [{configmap_name}] configmap");
@@ -73,19 +99,40 @@ fn handle_configmap_event(cm: &ConfigMap, vcl: &Rc<RefCell<Vcl>>, configmap_name
                 false
             };
 
-            if snippet_updated || vcl_recv_snippet_updated {
-                if let Err(e) = update(&vcl.borrow()) {
-                    error!("Failed to update VCL with updated snippets: {e}");
-                    process::exit(1);
-                }
+            // The default probe of the configmap takes precedence over the
+            // command line one, an invalid value keeps the previous probe.
+            let configmap_probe = match data.and_then(|data| data.get(DEFAULT_PROBE_KEY)) {
+                Some(value) => match parse_probe(value) {
+                    Ok(probe) => probe,
+                    Err(e) => {
+                        error!("Invalid 'default_probe' in the [{configmap_name}] configmap: {e}");
+                        events.invalid_configmap(cm.object_ref(&()), e);
+                        vcl.borrow().configmap_probe.clone()
+                    }
+                },
+                None => None,
+            };
+            let probe_updated = vcl.borrow().configmap_probe != configmap_probe;
+            vcl.borrow_mut().configmap_probe = configmap_probe;
 
-                if let Err(e) = reload(&vcl.borrow()) {
-                    error!("Failed to reload VCL with updated snippets: {e}");
-                    process::exit(1);
-                }
+            if !(snippet_updated || vcl_recv_snippet_updated || probe_updated) {
+                return Ok(());
             }
+
+            let result = update(&vcl.borrow())
+                .map_err(|e| format!("Failed to update VCL with updated snippets: {e}"))
+                .and_then(|_| {
+                    reload(&vcl.borrow())
+                        .map_err(|e| format!("Failed to reload VCL with updated snippets: {e}"))
+                });
+
+            events.reloaded(cm.object_ref(&()), &result);
+            result
+        }
+        Some(_) => Ok(()),
+        None => {
+            warn!("Could not get the name of VCL configmap");
+            Ok(())
         }
-        Some(_) => {}
-        None => warn!("Could not get the name of VCL configmap"),
     }
 }
@@ -0,0 +1,169 @@
+use k8s_openapi::api::core::v1::ObjectReference;
+use kube::Client;
+use kube::runtime::events::{Event, EventType, Recorder, Reporter};
+use log::{debug, error};
+use std::env;
+
+pub const REASON_INVALID_INGRESS: &str = "InvalidIngress";
+pub const REASON_SKIPPED: &str = "Skipped";
+pub const REASON_INVALID_CONFIGMAP: &str = "InvalidConfigMap";
+pub const REASON_RELOADED: &str = "VCLReloaded";
+pub const REASON_RELOAD_FAILED: &str = "VCLReloadFailed";
+
+/// The API server rejects Event notes larger than 1kB.
+const MAX_NOTE_LEN: usize = 1024;
+
+const ACTION_PARSE: &str = "Parse";
+const ACTION_RELOAD: &str = "Reload";
+
+/// A Kubernetes Event waiting to be published.
+#[derive(Debug, Clone)]
+pub struct PendingEvent {
+    pub regarding: ObjectReference,
+    pub type_: EventType,
+    pub reason: &'static str,
+    pub action: &'static str,
+    pub note: String,
+}
+
+///
+/// Events collects the Kubernetes Events about the
+/// Ingresses and ConfigMap handled by the controller.
+///
+/// The Events are queued while handling a watch event
+/// and published afterwards, so that they can be emitted
+/// from the synchronous parts of the controller.
+///
+#[derive(Default)]
+pub struct Events {
+    /// Publishes the Events, none when they are only collected.
+    recorder: Option<Recorder>,
+
+    pub pending: Vec<PendingEvent>,
+}
+
+impl Events {
+    pub fn new(client: Client, controller_name: &str) -> Self {
+        let reporter = Reporter {
+            controller: controller_name.to_string(),
+            instance: env::var("POD_NAME").ok(),
+        };
+
+        Events {
+            recorder: Some(Recorder::new(client, reporter)),
+            pending: vec![],
+        }
+    }
+
+    /// The Ingress could not be translated into VCL.
+    pub fn invalid_ingress(&mut self, regarding: ObjectReference, note: String) {
+        self.push(
+            regarding,
+            EventType::Warning,
+            REASON_INVALID_INGRESS,
+            ACTION_PARSE,
+            note,
+        );
+    }
+
+    /// A value of the VCL configmap is invalid and was ignored.
+    pub fn invalid_configmap(&mut self, regarding: ObjectReference, note: String) {
+        self.push(
+            regarding,
+            EventType::Warning,
+            REASON_INVALID_CONFIGMAP,
+            ACTION_PARSE,
+            note,
+        );
+    }
+
+    /// The Ingress is no longer handled by the controller.
+    pub fn skipped(&mut self, regarding: ObjectReference, note: String) {
+        self.push(
+            regarding,
+            EventType::Normal,
+            REASON_SKIPPED,
+            ACTION_PARSE,
+            note,
+        );
+    }
+
+    /// The VCL was reloaded, or failed to, after the object changed.
+    pub fn reloaded(&mut self, regarding: ObjectReference, result: &Result<(), String>) {
+        match result {
+            Ok(()) => self.push(
+                regarding,
+                EventType::Normal,
+                REASON_RELOADED,
+                ACTION_RELOAD,
+                String::from("VCL reloaded"),
+            ),
+            Err(e) => self.push(
+                regarding,
+                EventType::Warning,
+                REASON_RELOAD_FAILED,
+                ACTION_RELOAD,
+                e.clone(),
+            ),
+        }
+    }
+
+    fn push(
+        &mut self,
+        regarding: ObjectReference,
+        type_: EventType,
+        reason: &'static str,
+        action: &'static str,
+        mut note: String,
+    ) {
+        if note.len() > MAX_NOTE_LEN {
+            let mut end = MAX_NOTE_LEN;
+            while !note.is_char_boundary(end) {
+                end -= 1;
+            }
+            note.truncate(end);
+        }
+
+        self.pending.push(PendingEvent {
+            regarding,
+            type_,
+            reason,
+            action,
+            note,
+        });
+    }
+
+    /// Drop the pending Events without publishing them.
+    pub fn discard(&mut self) {
+        self.pending.clear();
+    }
+
+    ///
+    /// Publish the pending Events, failures are only logged
+    /// as the Events are merely informative.
+    ///
+    pub async fn flush(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+
+        let Some(recorder) = &self.recorder else {
+            return;
+        };
+
+        for ev in pending {
+            let name = ev.regarding.name.clone().unwrap_or_default();
+            debug!("Publishing event [{}] for [{name}]", ev.reason);
+
+            let event = Event {
+                type_: ev.type_,
+                reason: ev.reason.to_string(),
+                note: Some(ev.note),
+                action: ev.action.to_string(),
+                secondary: None,
+            };
+
+            if let Err(e) = recorder.publish(&event, &ev.regarding).await {
+                error!("Failed to publish event [{}] for [{name}]: {e}", ev.reason);
+            }
+        }
+    }
+}
@@ -1,140 +1,535 @@
-use crate::vcl::{Backend, Vcl, reload, update};
-use futures::{StreamExt, TryStreamExt};
-use k8s_openapi::api::networking::v1::{Ingress, IngressLoadBalancerIngress};
-use kube::api::{ListParams, Patch, PatchParams};
+use crate::annotations::{
+    parse_auth, parse_backend_settings, parse_bypass, parse_cache_settings, parse_canary,
+    parse_match_conditions, parse_probe, parse_redirect, parse_rewrite_target, parse_ssl_redirect,
+    parse_www_redirect,
+};
+use crate::events::Events;
+use crate::ingress_class::{IngressClasses, LEGACY_CLASS_ANNOTATION};
+use crate::namespaces::WatchedNamespaces;
+use crate::ports::{SERVICE_NAME_LABEL, ServicePorts, service_key, slice_service_key};
+use crate::secrets::{AuthSecrets, SecretEvents};
+use crate::status::IngressStatus;
+use crate::vcl::{
+    Backend, DefaultBackend, Vcl, attach_canaries, reload, service_backend_name, sort_backends,
+    sort_default_backends, update, validate_backend, validate_host, www_redirects,
+};
+use futures::StreamExt;
+use futures::future;
+use futures::stream::{self, BoxStream};
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use k8s_openapi::api::networking::v1::{Ingress, IngressBackend, IngressClass, IngressSpec};
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
 use kube::runtime::watcher::Error as WatcherError;
 use kube::{
-    Api, Client,
+    Api, Client, Resource,
     runtime::{WatchStreamExt, watcher},
 };
 use log::{debug, error, info, warn};
-use serde_json::json;
+use std::collections::BTreeSet;
 use std::process;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-const VARNISH_CLASS: &str = "varnish";
+/// The backends collected from a single Ingress object.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct IngressBackends {
+    pub rules: Vec<Backend>,
+    pub default: Option<DefaultBackend>,
+
+    /// The TLS hosts redirected to HTTPS.
+    pub https_hosts: Vec<String>,
+
+    /// The hosts canonicalized by the www redirect.
+    pub www_hosts: Vec<String>,
+}
+
+/// Tracks whether the watched resources went
+/// through their initial listing.
+#[derive(Debug, Default)]
+struct SyncState {
+    /// Ingress watchers which did not finish their initial listing.
+    pending_ingresses: usize,
+    /// EndpointSlice watchers which did not finish their initial listing.
+    pending_slices: usize,
+    classes: bool,
+    namespaces: bool,
+}
+
+impl SyncState {
+    fn is_synced(&self) -> bool {
+        self.pending_ingresses == 0 && self.pending_slices == 0 && self.classes && self.namespaces
+    }
+}
+
+///
+/// IngressState holds what the Ingresses are parsed against,
+/// and the backends parsed from them.
+///
+pub struct IngressState {
+    pub classes: IngressClasses,
+    pub namespaces: WatchedNamespaces,
+    pub service_ports: ServicePorts,
+    pub auth_secrets: AuthSecrets,
+    pub backends: HashMap<String, IngressBackends>,
+    pub events: Events,
+}
+
+impl IngressState {
+    /// Parse the provided ingress, see [handle_ingress_event].
+    pub fn handle(&mut self, ingress: &Ingress) {
+        handle_ingress_event(
+            ingress,
+            &self.classes,
+            &self.namespaces,
+            &self.service_ports,
+            &self.auth_secrets,
+            &mut self.backends,
+            &mut self.events,
+        );
+    }
+
+    ///
+    /// Re-parse the provided ingresses, returns true if there was any.
+    ///
+    pub fn refresh<'a>(&mut self, refreshed: impl Iterator<Item = &'a Ingress>) -> bool {
+        let mut any = false;
+
+        for ingress in refreshed {
+            self.handle(ingress);
+            any = true;
+        }
+
+        any
+    }
+}
 
 pub async fn watch_ingresses(
     client: Client,
     vcl: &Rc<RefCell<Vcl<'_>>>,
     ingress_class_name: &str,
+    controller_name: &str,
+    label_filter: bool,
+    namespaces: WatchedNamespaces,
+    mut status: IngressStatus,
 ) -> Result<(), WatcherError> {
-    let ingress_api: Api<Ingress> = Api::all(client);
+    let ingress_class_api: Api<IngressClass> = Api::all(client.clone());
+
+    // Ingresses are selected by their class, the label
+    // filter is an optional server-side prefilter.
+    let mut ingress_config = watcher::Config::default();
+    if label_filter {
+        ingress_config =
+            ingress_config.labels(format!("kubernetes.io/ingress={ingress_class_name}").as_str());
+    }
 
-    let mut observer = watcher(
-        ingress_api,
-        watcher::Config::default()
-            .labels(format!("kubernetes.io/ingress={ingress_class_name}").as_str()),
-    )
-    .default_backoff()
-    .boxed();
+    let mut observer = log_errors(
+        "Ingress",
+        namespaces.watch::<Ingress>(&client, ingress_config),
+    );
 
-    let mut backends: HashMap<String, Vec<Backend>> = HashMap::new();
-    info!(
-        "Started watching ingresses of class: [{ingress_class_name}]",
+    // IngressClasses are watched so that the controller knows
+    // which classes it owns and whether one of them is the default.
+    let mut class_observer = log_errors(
+        "IngressClass",
+        watcher(ingress_class_api, watcher::Config::default())
+            .default_backoff()
+            .boxed(),
+    );
+
+    // Services are watched so that named ports referenced
+    // by the Ingress backends can be resolved to numbers.
+    let mut service_observer = log_errors(
+        "Service",
+        namespaces.watch::<Service>(&client, watcher::Config::default()),
     );
 
-    while let Some(ev) = observer.try_next().await.unwrap() {
-        match ev {
-            watcher::Event::Apply(ingress) => {
-                handle_ingress_event(&ingress, ingress_class_name, &mut backends);
-                reconcile_backends(vcl, &backends);
+    // EndpointSlices are watched so that the routes point at the
+    // ready pods directly, when rendering endpoint backends. Only the
+    // slices of the referenced services are watched, once the
+    // ingresses are listed.
+    let endpoint_backends = vcl.borrow().director.is_some();
+    let mut slice_observer = stream::pending().boxed();
+
+    // The Secrets referenced by the basic auth annotations are
+    // watched once the ingresses are listed, so that the credentials
+    // are re-rendered as soon as they are rotated. They do not hold
+    // the sync back, the ingresses referencing a Secret which is not
+    // listed yet keep their previous routes.
+    let mut secret_observer = stream::pending().boxed();
+
+    let mut namespace_observer = log_errors("Namespace", namespaces.watch_selected(&client));
+
+    let mut ingresses: HashMap<String, Ingress> = HashMap::new();
+    // The backends rendered by the last VCL reload.
+    let mut published: Option<HashMap<String, IngressBackends>> = None;
+    let mut sync = SyncState {
+        pending_ingresses: namespaces.watchers(),
+        pending_slices: if endpoint_backends {
+            namespaces.watchers()
+        } else {
+            0
+        },
+        classes: false,
+        namespaces: !namespaces.has_selector(),
+    };
+    let mut state = IngressState {
+        classes: IngressClasses::new(controller_name, ingress_class_name),
+        namespaces,
+        service_ports: ServicePorts::default(),
+        auth_secrets: AuthSecrets::new(vcl.borrow().digest),
+        backends: HashMap::new(),
+        events: Events::new(client.clone(), controller_name),
+    };
+
+    info!("Started watching ingresses of classes with controller: [{controller_name}]");
+
+    loop {
+        tokio::select! {
+            ev = observer.next() => {
+                let Some(ev) = ev else { break };
+
+                match ev {
+                    watcher::Event::Apply(ingress) => {
+                        // The Secret it references is watched first,
+                        // so that it is pending rather than missing.
+                        let key = ingress_key(&ingress);
+                        store_ingress(ingress, &mut ingresses);
+                        watch_auth_secrets(&client, &ingresses, &mut state, &mut secret_observer, &sync);
+                        watch_endpoint_slices(&client, &ingresses, &mut state, &mut slice_observer, &mut sync, endpoint_backends);
+                        state.handle(&ingresses[&key]);
+                        if sync.is_synced() {
+                            reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                                .await;
+                        }
+                    }
+                    watcher::Event::Delete(ingress) => {
+                        handle_ingress_delete(&ingress, &state.classes, &mut state.backends);
+                        ingresses.remove(&ingress_key(&ingress));
+                        watch_auth_secrets(&client, &ingresses, &mut state, &mut secret_observer, &sync);
+                        watch_endpoint_slices(&client, &ingresses, &mut state, &mut slice_observer, &mut sync, endpoint_backends);
+                        if sync.is_synced() {
+                            reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                                .await;
+                        }
+                    }
+                    watcher::Event::Init => {
+                        debug!("Initialization event received");
+                    }
+                    watcher::Event::InitApply(ingress) => {
+                        state.handle(&ingress);
+                        store_ingress(ingress, &mut ingresses);
+                    }
+                    watcher::Event::InitDone => {
+                        sync.pending_ingresses = sync.pending_ingresses.saturating_sub(1);
+                        watch_auth_secrets(&client, &ingresses, &mut state, &mut secret_observer, &sync);
+                        watch_endpoint_slices(&client, &ingresses, &mut state, &mut slice_observer, &mut sync, endpoint_backends);
+                        if sync.is_synced() {
+                            info!(
+                                "Finished processing initial ingress resources. Starting VCL reconciliation."
+                            );
+                            // Re-parse now that every resource is listed,
+                            // the earlier parse failures were discarded.
+                            state.refresh(ingresses.values());
+                            reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                                .await;
+                        }
+                    }
+                }
             }
-            watcher::Event::Delete(ingress) => {
-                handle_ingress_delete(&ingress, ingress_class_name, &mut backends);
-                reconcile_backends(vcl, &backends);
+            ev = class_observer.next() => {
+                let Some(ev) = ev else { break };
+
+                let refreshed = match ev {
+                    watcher::Event::Apply(class) => state.classes.apply(&class),
+                    watcher::Event::Delete(class) => state.classes.delete(&class),
+                    watcher::Event::InitApply(class) => {
+                        state.classes.apply(&class);
+                        false
+                    }
+                    watcher::Event::InitDone => {
+                        debug!("Finished processing initial ingress classes");
+                        sync.classes = true;
+                        true
+                    }
+                    watcher::Event::Init => false,
+                };
+
+                if refreshed {
+                    info!("Claimed ingress classes changed, re-parsing ingresses");
+                    state.refresh(ingresses.values());
+
+                    if sync.is_synced() {
+                        reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                            .await;
+                    }
+                }
             }
-            watcher::Event::Init => {
-                debug!("Initialization event received");
+            ev = namespace_observer.next() => {
+                let Some(ev) = ev else { break };
+
+                let refreshed = match ev {
+                    watcher::Event::Apply(ns) => state.namespaces.select(&ns),
+                    watcher::Event::Delete(ns) => state.namespaces.deselect(&ns),
+                    watcher::Event::InitApply(ns) => {
+                        state.namespaces.select(&ns);
+                        false
+                    }
+                    watcher::Event::InitDone => {
+                        debug!("Finished processing initial namespaces");
+                        sync.namespaces = true;
+                        true
+                    }
+                    watcher::Event::Init => false,
+                };
+
+                if refreshed {
+                    info!("Selected namespaces changed, re-parsing ingresses");
+                    watch_auth_secrets(&client, &ingresses, &mut state, &mut secret_observer, &sync);
+                    watch_endpoint_slices(&client, &ingresses, &mut state, &mut slice_observer, &mut sync, endpoint_backends);
+                    state.refresh(ingresses.values());
+
+                    if sync.is_synced() {
+                        reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                            .await;
+                    }
+                }
+            }
+            ev = service_observer.next() => {
+                let Some(ev) = ev else { break };
+
+                let refreshed = match ev {
+                    watcher::Event::Apply(svc) => {
+                        state.service_ports.apply(&svc)
+                            && state.refresh(service_ingresses(&service_key(&svc), &ingresses))
+                    }
+                    watcher::Event::Delete(svc) => {
+                        state.service_ports.delete(&svc)
+                            && state.refresh(service_ingresses(&service_key(&svc), &ingresses))
+                    }
+                    watcher::Event::InitApply(svc) => {
+                        state.service_ports.apply(&svc);
+                        false
+                    }
+                    watcher::Event::InitDone => {
+                        debug!("Finished processing initial services, re-parsing ingresses");
+                        state.refresh(ingresses.values());
+                        true
+                    }
+                    watcher::Event::Init => false,
+                };
+
+                if refreshed && sync.is_synced() {
+                    reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                        .await;
+                }
+            }
+            ev = slice_observer.next() => {
+                let Some(ev) = ev else { break };
+
+                let refreshed = match ev {
+                    watcher::Event::Apply(slice) => {
+                        state.service_ports.apply_slice(&slice)
+                            && slice_service_key(&slice)
+                                .is_some_and(|key| state.refresh(service_ingresses(&key, &ingresses)))
+                    }
+                    watcher::Event::Delete(slice) => {
+                        state.service_ports.delete_slice(&slice)
+                            && slice_service_key(&slice)
+                                .is_some_and(|key| state.refresh(service_ingresses(&key, &ingresses)))
+                    }
+                    watcher::Event::InitApply(slice) => {
+                        state.service_ports.apply_slice(&slice);
+                        false
+                    }
+                    watcher::Event::InitDone => {
+                        debug!("Finished processing initial endpoint slices, re-parsing ingresses");
+                        sync.pending_slices = sync.pending_slices.saturating_sub(1);
+                        state.refresh(ingresses.values());
+                        true
+                    }
+                    watcher::Event::Init => false,
+                };
+
+                if refreshed && sync.is_synced() {
+                    reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                        .await;
+                }
             }
-            watcher::Event::InitApply(ingress) => {
-                handle_ingress_event(&ingress, ingress_class_name, &mut backends);
+            ev = secret_observer.next() => {
+                let Some((key, ev)) = ev else { break };
+
+                if state.auth_secrets.handle(&key, ev)
+                    && state.refresh(secret_ingresses(&key, &ingresses))
+                    && sync.is_synced()
+                {
+                    reconcile_backends(vcl, &state.backends, &mut published, &ingresses, &mut state.events)
+                        .await;
+                }
             }
-            watcher::Event::InitDone => {
-                info!(
-                    "Finished processing initial ingress resources. Starting VCL reconciliation."
-                );
-                reconcile_backends(vcl, &backends);
+            Ok(()) = status.changed() => {
+                debug!("Addresses of the controller service changed");
             }
         }
+
+        // Before the initial listing completes the Services
+        // or EndpointSlices may not be known yet, the parse
+        // failures would be spurious. Every watcher re-parses the
+        // ingresses once it finishes its listing.
+        if sync.is_synced() {
+            state.events.flush().await;
+            update_ingress_status(&status, &mut ingresses, &state.backends).await;
+        } else {
+            state.events.discard();
+        }
     }
     Ok(())
 }
 
-pub async fn update_status(
-    client: Client,
-    load_balancer_ingress: Vec<IngressLoadBalancerIngress>,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let ingress_api: Api<Ingress> = Api::all(client.clone());
-
-    let ingresses = ingress_api
-        .list(&ListParams::default())
-        .await
-        .map_err(|err| {
-            error!("Error listing ingresses: {err:?}");
-            err
-        })?;
-
-    for ingress in ingresses.into_iter().filter(|ingress| {
-        ingress
-            .spec
-            .as_ref()
-            .and_then(|spec| spec.ingress_class_name.as_deref())
-            == Some(VARNISH_CLASS)
-    }) {
-        let name = ingress.metadata.name.as_deref().unwrap_or_default();
-        let namespace = ingress.metadata.namespace.as_deref().unwrap_or("default");
-
-        if ingress.status.is_none() {
-            warn!(
-                "Ingress [{name}] in namespace [{namespace}] has no status"
-            );
-            continue;
-        }
-
-        let patch = json!({
-            "status": {
-                "loadBalancer": {
-                    "ingress": load_balancer_ingress
-                }
-            }
-        });
-
-        debug!("Applying ingress status patch {patch}");
-
-        let patch_params = PatchParams::apply("update-status");
-
-        let ingress_api_namespaced = Api::<Ingress>::namespaced(client.clone(), namespace);
+///
+/// Log and drop the errors of a watcher stream,
+/// the watcher itself retries with a backoff.
+///
+fn log_errors<K: Send + 'static>(
+    kind: &'static str,
+    events: BoxStream<'static, Result<watcher::Event<K>, WatcherError>>,
+) -> BoxStream<'static, watcher::Event<K>> {
+    events
+        .filter_map(move |ev| {
+            future::ready(ev.inspect_err(|e| error!("{kind} watcher error: {e}")).ok())
+        })
+        .boxed()
+}
 
-        match ingress_api_namespaced
-            .patch_status(name, &patch_params, &Patch::Merge(&patch))
-            .await
-        {
-            Ok(updated) => info!(
-                "Patched ingress: [{}]",
-                updated.metadata.name.unwrap_or_default()
-            ),
-            Err(err) => error!("Failed to patch ingress [{name}]: {err:?}"),
+///
+/// Advertise the controller addresses in the status of the
+/// handled Ingresses and clear them from the Ingresses which
+/// are no longer handled, the patched ones are stored right away
+/// so that they are not patched again before their event arrives.
+///
+async fn update_ingress_status(
+    status: &IngressStatus,
+    ingresses: &mut HashMap<String, Ingress>,
+    backends: &HashMap<String, IngressBackends>,
+) {
+    for (key, ingress) in ingresses.iter_mut() {
+        // The Ingresses which are no longer served, e.g. which lost
+        // their class or namespace, stop advertising the addresses.
+        let patched = if backends.contains_key(key) {
+            status.update(ingress).await
+        } else {
+            status.clear(ingress).await
+        };
+
+        if let Some(patched) = patched {
+            *ingress = patched;
         }
     }
-
-    Ok(())
 }
 
-fn is_varnish_class(ing: &Ingress, ingress_class_name: &str) -> bool {
-    if let Some(spec) = &ing.spec
-        && let Some(class_name) = &spec.ingress_class_name {
-            return class_name.eq_ignore_ascii_case(ingress_class_name);
-        }
-    false
+///
+/// Whether the Ingress belongs to one of the IngressClasses
+/// claimed by this controller.
+///
+/// The spec.ingressClassName takes precedence over the legacy
+/// kubernetes.io/ingress.class annotation. Ingresses with neither
+/// are adopted when one of the claimed classes is the default.
+///
+fn is_varnish_class(ing: &Ingress, classes: &IngressClasses) -> bool {
+    if let Some(class_name) = ing
+        .spec
+        .as_ref()
+        .and_then(|spec| spec.ingress_class_name.as_deref())
+    {
+        return classes.is_claimed(class_name);
+    }
+
+    match ing
+        .metadata
+        .annotations
+        .as_ref()
+        .and_then(|annotations| annotations.get(LEGACY_CLASS_ANNOTATION))
+    {
+        Some(class_name) => classes.is_legacy_claimed(class_name),
+        None => classes.has_default(),
+    }
 }
 
-fn parse_ingress_spec(ing: Ingress) -> Result<Vec<Backend>, String> {
+pub fn parse_ingress_spec(
+    ing: Ingress,
+    service_ports: &ServicePorts,
+    auth_secrets: &AuthSecrets,
+) -> Result<IngressBackends, String> {
     let mut backends = Vec::new();
 
     let spec = match ing.spec {
         Some(spec) => spec,
-        None => return Ok(backends),
+        None => return Ok(IngressBackends::default()),
+    };
+
+    let cache = parse_cache_settings(&ing.metadata)?;
+    let bypass = parse_bypass(&ing.metadata)?;
+    let probe = parse_probe(&ing.metadata)?;
+    let settings = parse_backend_settings(&ing.metadata)?;
+    let canary = parse_canary(&ing.metadata)?;
+    let conditions = parse_match_conditions(&ing.metadata)?;
+    let rewrite = parse_rewrite_target(&ing.metadata)?;
+    let redirect = parse_redirect(&ing.metadata)?;
+
+    let namespace = ing.metadata.namespace.as_deref().unwrap_or("default");
+    let auth = parse_auth(&ing.metadata)?
+        .map(|mut auth| {
+            let credentials = auth_secrets.credentials(namespace, &auth.secret)?;
+            auth.credentials = credentials.headers;
+            auth.sha1 = credentials.sha1;
+            Ok::<_, String>(auth)
+        })
+        .transpose()?;
+
+    let rule_hosts: Vec<String> = spec
+        .rules
+        .iter()
+        .flatten()
+        .filter_map(|rule| rule.host.clone())
+        .collect();
+
+    // A TLS entry without hosts covers all the hosts of the rules.
+    let mut https_hosts = Vec::new();
+    if parse_ssl_redirect(&ing.metadata)? {
+        for tls in spec.tls.iter().flatten() {
+            https_hosts.extend(tls.hosts.clone().unwrap_or_else(|| rule_hosts.clone()));
+        }
+    }
+    for host in &https_hosts {
+        validate_host(host)?;
+    }
+
+    let www_hosts = if parse_www_redirect(&ing.metadata)? {
+        rule_hosts
+    } else {
+        vec![]
+    };
+
+    // The requests matching none of the rules are never split,
+    // a canary Ingress only contributes to the primary routes.
+    let default = match &spec.default_backend {
+        Some(_) if canary.is_some() => {
+            warn!(
+                "Default backend of canary ingress [{}] is ignored",
+                ing.metadata.name.as_deref().unwrap_or("default")
+            );
+            None
+        }
+        Some(default_backend) => {
+            parse_default_backend(&ing.metadata, &spec, default_backend, service_ports)?.map(
+                |mut default| {
+                    default.probe = probe.clone();
+                    default.settings = settings.clone();
+                    default.auth = auth.clone();
+                    default
+                },
+            )
+        }
+        None => None,
     };
 
     if let Some(rules) = spec.rules {
@@ -146,30 +541,33 @@ fn parse_ingress_spec(ing: Ingress) -> Result<Vec<Backend>, String> {
 
             for path in &http.paths {
                 if let Some(backend_service) = &path.backend.service {
-                    let namespace = ing.metadata.namespace.as_deref().unwrap_or("default");
                     let host = rule.host.as_deref().unwrap_or("");
                     let path_str = path.path.as_deref().unwrap_or("/");
-                    let backend_name = format!(
-                        "{}-{}-{}",
-                        namespace,
-                        ing.metadata.name.as_deref().unwrap_or("default"),
-                        backend_service.name
-                    );
-                    let port = backend_service
-                        .port
-                        .as_ref()
-                        .and_then(|p| p.number)
-                        .ok_or("Port number is missing")?;
+                    let port = service_ports.resolve(namespace, backend_service)?;
+                    let backend_name = service_backend_name(namespace, &backend_service.name, port);
 
-                    let backend = Backend::new(
+                    let mut backend = Backend::new(
                         namespace.to_string(),
                         backend_name.clone(),
                         host.to_string(),
                         path_str.to_string(),
                         backend_service.name.clone(),
                         path.path_type.clone(),
-                        port as u16,
+                        port,
                     );
+                    backend.conditions = conditions.clone();
+                    backend.cache = cache.clone();
+                    backend.bypass = bypass.as_ref().and_then(|b| b.mode_for(path_str));
+                    backend.rewrite = rewrite.clone();
+                    backend.redirect = redirect.clone();
+                    backend.auth = auth.clone();
+                    backend.probe = probe.clone();
+                    backend.settings = settings.clone();
+                    backend.endpoints =
+                        service_ports.endpoints(namespace, &backend_service.name, port);
+                    backend.canary_weight = canary;
+
+                    validate_backend(&backend)?;
 
                     info!(
                         "Found backend [{}] from ingress [{}]",
@@ -182,42 +580,146 @@ fn parse_ingress_spec(ing: Ingress) -> Result<Vec<Backend>, String> {
         }
     }
 
-    Ok(backends)
+    if let Some(bypass) = &bypass
+        && let Some(path) = bypass
+            .paths
+            .iter()
+            .find(|path| !backends.iter().any(|b| &b.path == *path))
+    {
+        return Err(format!(
+            "Bypass path [{}] does not match any of the Ingress paths",
+            path.escape_debug()
+        ));
+    }
+
+    Ok(IngressBackends {
+        rules: backends,
+        default,
+        https_hosts,
+        www_hosts,
+    })
+}
+
+fn parse_default_backend(
+    metadata: &ObjectMeta,
+    spec: &IngressSpec,
+    default_backend: &IngressBackend,
+    service_ports: &ServicePorts,
+) -> Result<Option<DefaultBackend>, String> {
+    let backend_service = match &default_backend.service {
+        Some(service) => service,
+        None => {
+            warn!(
+                "Default backend of ingress [{}] is not a service, ignoring it",
+                metadata.name.as_deref().unwrap_or("default")
+            );
+            return Ok(None);
+        }
+    };
+
+    let namespace = metadata.namespace.as_deref().unwrap_or("default");
+    let port = service_ports.resolve(namespace, backend_service)?;
+    let backend_name = service_backend_name(namespace, &backend_service.name, port);
+
+    let mut hosts: Vec<String> = spec
+        .rules
+        .iter()
+        .flatten()
+        .filter_map(|rule| rule.host.clone())
+        .collect();
+    hosts.sort();
+    hosts.dedup();
+
+    for host in &hosts {
+        validate_host(host)?;
+    }
+
+    info!(
+        "Found default backend [{}] from ingress [{}]",
+        backend_name,
+        metadata.name.as_deref().unwrap_or("default")
+    );
+
+    let mut default = DefaultBackend::new(
+        namespace.to_string(),
+        backend_name,
+        hosts,
+        backend_service.name.clone(),
+        port,
+    );
+    default.endpoints = service_ports.endpoints(namespace, &backend_service.name, port);
+
+    Ok(Some(default))
 }
 
-fn handle_ingress_event(
+pub fn handle_ingress_event(
     ingress: &Ingress,
-    ingress_class_name: &str,
-    backends: &mut HashMap<String, Vec<Backend>>,
+    classes: &IngressClasses,
+    namespaces: &WatchedNamespaces,
+    service_ports: &ServicePorts,
+    auth_secrets: &AuthSecrets,
+    backends: &mut HashMap<String, IngressBackends>,
+    events: &mut Events,
 ) {
-    let ing_name = ingress.metadata.name.as_deref().unwrap_or("default");
+    let ing_name = ingress_key(ingress);
+
+    if !namespaces.contains(ingress.metadata.namespace.as_deref().unwrap_or("default")) {
+        debug!("Skipping ingress [{ing_name}], its namespace is not watched.");
+        if backends.remove(&ing_name).is_some() {
+            events.skipped(
+                ingress.object_ref(&()),
+                String::from("Its namespace is no longer watched by the controller"),
+            );
+        }
+        return;
+    }
 
-    if !is_varnish_class(ingress, ingress_class_name) {
+    if !is_varnish_class(ingress, classes) {
         info!(
             "Skipping ingress [{ing_name}], it does not have the Varnish class."
         );
+        if backends.remove(&ing_name).is_some() {
+            events.skipped(
+                ingress.object_ref(&()),
+                String::from("It no longer has an ingress class claimed by the controller"),
+            );
+        }
+        return;
+    }
+
+    if let Ok(Some(auth)) = parse_auth(&ingress.metadata)
+        && auth_secrets.is_pending(
+            ingress.metadata.namespace.as_deref().unwrap_or("default"),
+            &auth.secret,
+        )
+    {
+        debug!("Skipping ingress [{ing_name}], its auth secret is not listed yet.");
         return;
     }
 
     info!("Parsing ingress [{ing_name}]");
-    match parse_ingress_spec(ingress.clone()) {
+    match parse_ingress_spec(ingress.clone(), service_ports, auth_secrets) {
         Ok(bbs) => {
-            backends.insert(ing_name.to_string(), bbs);
+            backends.insert(ing_name, bbs);
         }
         Err(e) => {
-            error!("Error parsing ingress [{ing_name}]: {e}");
+            error!("Error parsing ingress [{ing_name}], skipping it: {e}");
+            events.invalid_ingress(
+                ingress.object_ref(&()),
+                format!("Skipping the ingress, its routes were not updated: {e}"),
+            );
         }
     }
 }
 
-fn handle_ingress_delete(
+pub fn handle_ingress_delete(
     ingress: &Ingress,
-    ingress_class_name: &str,
-    backends: &mut HashMap<String, Vec<Backend>>,
+    classes: &IngressClasses,
+    backends: &mut HashMap<String, IngressBackends>,
 ) {
-    let ing_name = ingress.metadata.name.as_deref().unwrap_or_default();
+    let ing_name = ingress_key(ingress);
 
-    if !is_varnish_class(ingress, ingress_class_name) {
+    if !is_varnish_class(ingress, classes) {
         info!(
             "Skipping ingress [{ing_name}], it does not have the Varnish class."
         );
@@ -225,21 +727,267 @@ fn handle_ingress_delete(
     }
 
     warn!("Deleting ingress [{ing_name}]");
-    backends.remove(ing_name);
+    backends.remove(&ing_name);
+}
+
+fn store_ingress(ingress: Ingress, ingresses: &mut HashMap<String, Ingress>) {
+    ingresses.insert(ingress_key(&ingress), ingress);
+}
+
+///
+/// The identity of an Ingress object, <namespace>/<name>.
+///
+/// Ingress names are only unique within a namespace, so the
+/// controller state must never be keyed by the bare name.
+///
+fn ingress_key(ing: &Ingress) -> String {
+    format!(
+        "{}/{}",
+        ing.metadata.namespace.as_deref().unwrap_or("default"),
+        ing.metadata.name.as_deref().unwrap_or_default()
+    )
+}
+
+///
+/// The ingresses which reference the service identified by <namespace>/<name>.
+///
+fn service_ingresses<'a>(
+    svc_key: &'a str,
+    ingresses: &'a HashMap<String, Ingress>,
+) -> impl Iterator<Item = &'a Ingress> {
+    let (svc_namespace, svc_name) = svc_key.split_once('/').unwrap_or_default();
+
+    ingresses
+        .values()
+        .filter(move |ingress| {
+            ingress.metadata.namespace.as_deref().unwrap_or("default") == svc_namespace
+                && references_service(ingress, svc_name)
+        })
+        .inspect(move |ingress| {
+            info!(
+                "Service [{svc_key}] changed, re-parsing ingress [{}]",
+                ingress_key(ingress)
+            );
+        })
+}
+
+///
+/// Watch the Secrets referenced by the basic auth annotations of
+/// the ingresses, once they are listed, whenever they change.
+///
+/// The ingresses referencing a Secret are re-parsed once it is listed.
+///
+fn watch_auth_secrets(
+    client: &Client,
+    ingresses: &HashMap<String, Ingress>,
+    state: &mut IngressState,
+    secret_observer: &mut SecretEvents,
+    sync: &SyncState,
+) {
+    if sync.pending_ingresses > 0 {
+        return;
+    }
+
+    let referenced = ingresses
+        .values()
+        .filter_map(|ingress| {
+            let namespace = ingress.metadata.namespace.as_deref().unwrap_or("default");
+            let auth = parse_auth(&ingress.metadata).ok()??;
+            state
+                .namespaces
+                .contains(namespace)
+                .then(|| format!("{namespace}/{}", auth.secret))
+        })
+        .collect();
+
+    if let Some(observer) = state.auth_secrets.watch(client, referenced) {
+        info!(
+            "Referenced secrets changed, watching [{}] secrets",
+            state.auth_secrets.watchers()
+        );
+        *secret_observer = observer;
+    }
 }
 
-fn reconcile_backends(v: &Rc<RefCell<Vcl>>, backends: &HashMap<String, Vec<Backend>>) {
-    let backends_list = backends.values().flatten().cloned().collect();
+///
+/// Watch the EndpointSlices of the services referenced by the
+/// ingresses, once they are listed, whenever new ones are referenced.
+///
+/// The slices are selected by their service name label, so that
+/// neither the controller memory nor the API server traffic cover
+/// the services which are not routed to.
+///
+fn watch_endpoint_slices(
+    client: &Client,
+    ingresses: &HashMap<String, Ingress>,
+    state: &mut IngressState,
+    slice_observer: &mut BoxStream<'static, watcher::Event<EndpointSlice>>,
+    sync: &mut SyncState,
+    endpoint_backends: bool,
+) {
+    if !endpoint_backends || sync.pending_ingresses > 0 {
+        return;
+    }
+
+    let referenced: BTreeSet<String> = ingresses
+        .values()
+        .filter(|ingress| {
+            state
+                .namespaces
+                .contains(ingress.metadata.namespace.as_deref().unwrap_or("default"))
+        })
+        .flat_map(|ingress| {
+            let namespace = ingress.metadata.namespace.as_deref().unwrap_or("default");
+            referenced_services(ingress).map(move |service| format!("{namespace}/{service}"))
+        })
+        .collect();
+
+    let names: BTreeSet<&str> = referenced
+        .iter()
+        .filter_map(|key| key.split_once('/').map(|(_, name)| name))
+        .collect();
+    let selector = format!(
+        "{SERVICE_NAME_LABEL} in ({})",
+        names.iter().copied().collect::<Vec<_>>().join(",")
+    );
+    let no_service = names.is_empty();
 
+    if !state.service_ports.track_services(referenced) {
+        return;
+    }
+
+    if no_service {
+        *slice_observer = stream::pending().boxed();
+        sync.pending_slices = 0;
+        return;
+    }
+
+    info!("Referenced services changed, watching their endpoint slices");
+    *slice_observer = log_errors(
+        "EndpointSlice",
+        state
+            .namespaces
+            .watch::<EndpointSlice>(client, watcher::Config::default().labels(&selector)),
+    );
+
+    // Once listed, the known endpoints are kept while the
+    // slices of the newly referenced services are listed.
+    if sync.pending_slices > 0 {
+        sync.pending_slices = state.namespaces.watchers();
+    }
+}
+
+///
+/// The ingresses whose basic auth references the Secret
+/// identified by <namespace>/<name>.
+///
+fn secret_ingresses<'a>(
+    secret_key: &'a str,
+    ingresses: &'a HashMap<String, Ingress>,
+) -> impl Iterator<Item = &'a Ingress> {
+    let (secret_namespace, secret_name) = secret_key.split_once('/').unwrap_or_default();
+
+    ingresses
+        .values()
+        .filter(move |ingress| {
+            ingress.metadata.namespace.as_deref().unwrap_or("default") == secret_namespace
+                && parse_auth(&ingress.metadata)
+                    .is_ok_and(|auth| auth.is_some_and(|auth| auth.secret == secret_name))
+        })
+        .inspect(move |ingress| {
+            info!(
+                "Secret [{secret_key}] changed, re-parsing ingress [{}]",
+                ingress_key(ingress)
+            );
+        })
+}
+
+fn references_service(ing: &Ingress, svc_name: &str) -> bool {
+    referenced_services(ing).any(|service| service == svc_name)
+}
+
+///
+/// The names of the services referenced by the backends of an Ingress.
+///
+fn referenced_services(ing: &Ingress) -> impl Iterator<Item = &str> {
+    let spec = ing.spec.iter();
+
+    let default_backend = spec.clone().flat_map(|spec| spec.default_backend.iter());
+    let rule_backends = spec
+        .flat_map(|spec| spec.rules.iter().flatten())
+        .filter_map(|rule| rule.http.as_ref())
+        .flat_map(|http| http.paths.iter().map(|path| &path.backend));
+
+    default_backend
+        .chain(rule_backends)
+        .filter_map(|backend| backend.service.as_ref())
+        .map(|service| service.name.as_str())
+}
+
+///
+/// Render the backends into the VCL and reload it, unless they
+/// did not change since the last reload.
+///
+/// The outcome is recorded as an Event against the Ingresses whose
+/// backends changed, a failure is fatal.
+///
+async fn reconcile_backends(
+    v: &Rc<RefCell<Vcl<'_>>>,
+    backends: &HashMap<String, IngressBackends>,
+    published: &mut Option<HashMap<String, IngressBackends>>,
+    ingresses: &HashMap<String, Ingress>,
+    events: &mut Events,
+) {
+    if published.as_ref() == Some(backends) {
+        debug!("Backends unchanged, skipping the VCL reload");
+        return;
+    }
+
+    let previous = published.take().unwrap_or_default();
+
+    let mut backends_list: Vec<Backend> = backends
+        .values()
+        .flat_map(|b| b.rules.iter())
+        .cloned()
+        .collect();
+    sort_backends(&mut backends_list);
+    attach_canaries(&mut backends_list);
+
+    let mut default_backends: Vec<DefaultBackend> = backends
+        .values()
+        .filter_map(|b| b.default.clone())
+        .collect();
+    sort_default_backends(&mut default_backends);
+
+    let https_hosts: BTreeSet<String> = backends
+        .values()
+        .flat_map(|b| b.https_hosts.iter().cloned())
+        .collect();
+    let www_hosts: Vec<String> = backends
+        .values()
+        .flat_map(|b| b.www_hosts.iter().cloned())
+        .collect();
+
+    v.borrow_mut().https_hosts = https_hosts.into_iter().collect();
+    v.borrow_mut().www_redirects = www_redirects(&www_hosts, &backends_list, &default_backends);
     v.borrow_mut().backends = backends_list;
+    v.borrow_mut().default_backends = default_backends;
 
-    if let Err(e) = update(&v.borrow()) {
-        error!("{e}");
-        process::exit(1);
+    let result = update(&v.borrow())
+        .and_then(|_| reload(&v.borrow()))
+        .map_err(|e| e.to_string());
+
+    for (key, ingress) in ingresses {
+        if backends.get(key) != previous.get(key) {
+            events.reloaded(ingress.object_ref(&()), &result);
+        }
     }
 
-    if let Err(e) = reload(&v.borrow()) {
+    if let Err(e) = result {
         error!("{e}");
+        events.flush().await;
         process::exit(1);
     }
+
+    *published = Some(backends.clone());
 }
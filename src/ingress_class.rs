@@ -0,0 +1,91 @@
+use k8s_openapi::api::networking::v1::IngressClass;
+use std::collections::BTreeMap;
+
+pub const DEFAULT_CLASS_ANNOTATION: &str = "ingressclass.kubernetes.io/is-default-class";
+pub const LEGACY_CLASS_ANNOTATION: &str = "kubernetes.io/ingress.class";
+
+///
+/// IngressClasses keeps track of the IngressClass objects
+/// claimed by this controller, i.e. the ones whose
+/// spec.controller matches the configured controller name.
+///
+/// https://kubernetes.io/docs/concepts/services-networking/ingress/#ingress-class
+#[derive(Debug, Default)]
+pub struct IngressClasses {
+    /// The controller name as found in
+    /// the IngressClass spec.controller.
+    controller: String,
+
+    /// The class name matched against the legacy
+    /// kubernetes.io/ingress.class annotation.
+    legacy_class: String,
+
+    /// Claimed IngressClass names and whether
+    /// they are marked as the default class.
+    claimed: BTreeMap<String, bool>,
+}
+
+impl IngressClasses {
+    pub fn new(controller: &str, legacy_class: &str) -> Self {
+        IngressClasses {
+            controller: controller.to_string(),
+            legacy_class: legacy_class.to_string(),
+            claimed: BTreeMap::new(),
+        }
+    }
+
+    ///
+    /// Claim or release the provided IngressClass, depending
+    /// on whether its controller matches this controller.
+    ///
+    /// Returns true when the claimed classes changed.
+    ///
+    pub fn apply(&mut self, class: &IngressClass) -> bool {
+        let name = class.metadata.name.clone().unwrap_or_default();
+
+        let controller = class
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.controller.as_deref());
+
+        if controller != Some(self.controller.as_str()) {
+            return self.claimed.remove(&name).is_some();
+        }
+
+        let is_default = class
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(DEFAULT_CLASS_ANNOTATION))
+            .is_some_and(|value| value == "true");
+
+        self.claimed.insert(name, is_default) != Some(is_default)
+    }
+
+    ///
+    /// Release the provided IngressClass.
+    ///
+    /// Returns true when the claimed classes changed.
+    ///
+    pub fn delete(&mut self, class: &IngressClass) -> bool {
+        let name = class.metadata.name.as_deref().unwrap_or_default();
+        self.claimed.remove(name).is_some()
+    }
+
+    /// Whether the IngressClass with the provided name is claimed.
+    pub fn is_claimed(&self, name: &str) -> bool {
+        self.claimed.contains_key(name)
+    }
+
+    /// Whether the value of the legacy kubernetes.io/ingress.class
+    /// annotation designates this controller.
+    pub fn is_legacy_claimed(&self, name: &str) -> bool {
+        name == self.legacy_class || self.is_claimed(name)
+    }
+
+    /// Whether one of the claimed IngressClasses is the default one,
+    /// in which case Ingresses without a class are adopted.
+    pub fn has_default(&self) -> bool {
+        self.claimed.values().any(|is_default| *is_default)
+    }
+}
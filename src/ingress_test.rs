@@ -0,0 +1,587 @@
+#[cfg(test)]
+mod test {
+    use crate::annotations::{
+        AUTH_SECRET_ANNOTATION, BYPASS_ANNOTATION, BYPASS_PATHS_ANNOTATION, CANARY_ANNOTATION,
+        CANARY_WEIGHT_ANNOTATION, SSL_REDIRECT_ANNOTATION, WWW_REDIRECT_ANNOTATION,
+    };
+    use crate::events::{Events, REASON_INVALID_INGRESS, REASON_SKIPPED};
+    use crate::ingress::{
+        IngressBackends, IngressState, handle_ingress_delete, handle_ingress_event,
+        parse_ingress_spec,
+    };
+    use crate::ingress_class::{DEFAULT_CLASS_ANNOTATION, IngressClasses, LEGACY_CLASS_ANNOTATION};
+    use crate::namespaces::WatchedNamespaces;
+    use crate::ports::ServicePorts;
+    use crate::secrets::{AUTH_KEY, AuthSecrets};
+    use k8s_openapi::ByteString;
+    use k8s_openapi::api::core::v1::{Namespace, Secret};
+    use k8s_openapi::api::networking::v1::{
+        HTTPIngressPath, HTTPIngressRuleValue, Ingress, IngressBackend, IngressClass,
+        IngressClassSpec, IngressRule, IngressServiceBackend, IngressSpec, IngressTLS,
+        ServiceBackendPort,
+    };
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use kube::runtime::watcher::Error as WatcherError;
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+    const CONTROLLER: &str = "varnish.io/varnish-ingress-controller";
+
+    fn ingress_class(name: &str, controller: &str, is_default: bool) -> IngressClass {
+        IngressClass {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                annotations: Some(BTreeMap::from([(
+                    DEFAULT_CLASS_ANNOTATION.to_string(),
+                    is_default.to_string(),
+                )])),
+                ..Default::default()
+            },
+            spec: Some(IngressClassSpec {
+                controller: Some(controller.to_string()),
+                parameters: None,
+            }),
+        }
+    }
+
+    fn varnish_classes() -> IngressClasses {
+        let mut classes = IngressClasses::new(CONTROLLER, "varnish");
+        classes.apply(&ingress_class("varnish", CONTROLLER, false));
+        classes
+    }
+
+    fn ingress(namespace: &str, name: &str, host: &str) -> Ingress {
+        ingress_with_path(namespace, name, host, "/")
+    }
+
+    fn ingress_with_path(namespace: &str, name: &str, host: &str, path: &str) -> Ingress {
+        Ingress {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(namespace.to_string()),
+                ..Default::default()
+            },
+            spec: Some(IngressSpec {
+                ingress_class_name: Some(String::from("varnish")),
+                rules: Some(vec![IngressRule {
+                    host: Some(host.to_string()),
+                    http: Some(HTTPIngressRuleValue {
+                        paths: vec![HTTPIngressPath {
+                            path: Some(path.to_string()),
+                            path_type: String::from("Prefix"),
+                            backend: IngressBackend {
+                                service: Some(IngressServiceBackend {
+                                    name: String::from("web"),
+                                    port: Some(ServiceBackendPort {
+                                        number: Some(80),
+                                        name: None,
+                                    }),
+                                }),
+                                resource: None,
+                            },
+                        }],
+                    }),
+                }]),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_ingresses_keyed_by_namespace() {
+        let classes = varnish_classes();
+        let namespaces = WatchedNamespaces::default();
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+        let mut backends: HashMap<String, IngressBackends> = HashMap::new();
+        let mut events = Events::default();
+
+        let alpha = ingress("alpha", "web", "alpha.foo.com");
+        let beta = ingress("beta", "web", "beta.foo.com");
+
+        handle_ingress_event(
+            &alpha,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &beta,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+
+        assert_eq!(backends.len(), 2);
+        assert_eq!(backends["alpha/web"].rules[0].host, "alpha.foo.com");
+        assert_eq!(backends["beta/web"].rules[0].host, "beta.foo.com");
+
+        handle_ingress_delete(&alpha, &classes, &mut backends);
+
+        assert_eq!(backends.len(), 1);
+        assert!(backends.contains_key("beta/web"));
+    }
+
+    #[test]
+    fn test_unsafe_ingress_skipped() {
+        let classes = varnish_classes();
+        let namespaces = WatchedNamespaces::default();
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+        let mut backends: HashMap<String, IngressBackends> = HashMap::new();
+        let mut events = Events::default();
+
+        let unsafe_path = ingress_with_path("alpha", "web", "alpha.foo.com", "/foo\n}");
+        let unsafe_host = ingress("beta", "web", "beta.foo.com\" || true || \"");
+
+        handle_ingress_event(
+            &unsafe_path,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &unsafe_host,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+
+        assert!(backends.is_empty());
+        assert_eq!(events.pending.len(), 2);
+        assert!(
+            events
+                .pending
+                .iter()
+                .all(|ev| ev.reason == REASON_INVALID_INGRESS)
+        );
+        assert_eq!(events.pending[0].regarding.name.as_deref(), Some("web"));
+        assert_eq!(
+            events.pending[0].regarding.namespace.as_deref(),
+            Some("alpha")
+        );
+    }
+
+    #[test]
+    fn test_ingress_classes() {
+        let mut classes = IngressClasses::new(CONTROLLER, "varnish");
+        let namespaces = WatchedNamespaces::default();
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+        let mut backends: HashMap<String, IngressBackends> = HashMap::new();
+        let mut events = Events::default();
+
+        let mut classless = ingress("alpha", "web", "alpha.foo.com");
+        classless.spec.as_mut().unwrap().ingress_class_name = None;
+        let varnish = ingress("beta", "web", "beta.foo.com");
+
+        assert!(!classes.apply(&ingress_class("nginx", "k8s.io/ingress-nginx", true)));
+        assert!(classes.apply(&ingress_class("varnish", CONTROLLER, false)));
+        assert!(!classes.apply(&ingress_class("varnish", CONTROLLER, false)));
+
+        handle_ingress_event(
+            &classless,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &varnish,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert_eq!(backends.len(), 1);
+        assert!(backends.contains_key("beta/web"));
+
+        assert!(classes.apply(&ingress_class("varnish", CONTROLLER, true)));
+
+        handle_ingress_event(
+            &classless,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert_eq!(backends.len(), 2);
+        assert!(backends.contains_key("alpha/web"));
+
+        assert!(classes.apply(&ingress_class("varnish", "k8s.io/ingress-nginx", true)));
+
+        handle_ingress_event(
+            &classless,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &varnish,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert!(backends.is_empty());
+
+        // Only the ingresses which were handled are reported as skipped.
+        let skipped: Vec<_> = events
+            .pending
+            .iter()
+            .filter(|ev| ev.reason == REASON_SKIPPED)
+            .collect();
+        assert_eq!(skipped.len(), 2);
+    }
+
+    #[test]
+    fn test_legacy_class_annotation() {
+        let classes = varnish_classes();
+        let namespaces = WatchedNamespaces::default();
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+        let mut backends: HashMap<String, IngressBackends> = HashMap::new();
+        let mut events = Events::default();
+
+        let mut legacy = ingress("alpha", "web", "alpha.foo.com");
+        legacy.spec.as_mut().unwrap().ingress_class_name = None;
+        legacy.metadata.annotations = Some(BTreeMap::from([(
+            LEGACY_CLASS_ANNOTATION.to_string(),
+            String::from("varnish"),
+        )]));
+
+        let mut other = legacy.clone();
+        other.metadata.namespace = Some(String::from("beta"));
+        other.metadata.annotations = Some(BTreeMap::from([(
+            LEGACY_CLASS_ANNOTATION.to_string(),
+            String::from("nginx"),
+        )]));
+
+        // spec.ingressClassName takes precedence over the annotation.
+        let mut overridden = other.clone();
+        overridden.metadata.namespace = Some(String::from("gamma"));
+        overridden.spec.as_mut().unwrap().ingress_class_name = Some(String::from("varnish"));
+
+        handle_ingress_event(
+            &legacy,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &other,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &overridden,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+
+        assert_eq!(backends.len(), 2);
+        assert!(backends.contains_key("alpha/web"));
+        assert!(backends.contains_key("gamma/web"));
+    }
+
+    #[test]
+    fn test_watched_namespaces() {
+        let classes = varnish_classes();
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+        let mut backends: HashMap<String, IngressBackends> = HashMap::new();
+        let mut events = Events::default();
+
+        let alpha = ingress("alpha", "web", "alpha.foo.com");
+        let beta = ingress("beta", "web", "beta.foo.com");
+
+        let mut namespaces =
+            WatchedNamespaces::new(&[String::from("alpha"), String::from("beta")], "team=web");
+
+        handle_ingress_event(
+            &alpha,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &beta,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert!(backends.is_empty());
+
+        let selected = Namespace {
+            metadata: ObjectMeta {
+                name: Some(String::from("beta")),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(namespaces.select(&selected));
+        assert!(!namespaces.select(&selected));
+
+        handle_ingress_event(
+            &alpha,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        handle_ingress_event(
+            &beta,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert_eq!(backends.len(), 1);
+        assert!(backends.contains_key("beta/web"));
+
+        assert!(namespaces.deselect(&selected));
+        handle_ingress_event(
+            &beta,
+            &classes,
+            &namespaces,
+            &service_ports,
+            &auth_secrets,
+            &mut backends,
+            &mut events,
+        );
+        assert!(backends.is_empty());
+
+        let listed = WatchedNamespaces::new(&[String::from("alpha")], "");
+        assert!(listed.contains("alpha"));
+        assert!(!listed.contains("beta"));
+        assert_eq!(listed.watchers(), 1);
+        assert!(WatchedNamespaces::default().contains("beta"));
+    }
+
+    #[test]
+    fn test_bypass_paths() {
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+
+        let mut api = ingress_with_path("alpha", "web", "alpha.foo.com", "/api");
+        api.metadata.annotations = Some(BTreeMap::from([
+            (BYPASS_ANNOTATION.to_string(), String::from("pass")),
+            (BYPASS_PATHS_ANNOTATION.to_string(), String::from("/api")),
+        ]));
+
+        let backends = parse_ingress_spec(api.clone(), &service_ports, &auth_secrets).unwrap();
+        assert_eq!(backends.rules[0].bypass.as_deref(), Some("pass"));
+
+        api.metadata
+            .annotations
+            .as_mut()
+            .unwrap()
+            .insert(BYPASS_PATHS_ANNOTATION.to_string(), String::from("/web"));
+
+        let err = parse_ingress_spec(api, &service_ports, &auth_secrets).unwrap_err();
+        assert!(err.contains("/web"));
+    }
+
+    #[test]
+    fn test_canary_ingress() {
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+
+        let mut canary = ingress("alpha", "web-canary", "alpha.foo.com");
+        canary.metadata.annotations = Some(BTreeMap::from([
+            (CANARY_ANNOTATION.to_string(), String::from("true")),
+            (CANARY_WEIGHT_ANNOTATION.to_string(), String::from("10")),
+        ]));
+        canary.spec.as_mut().unwrap().default_backend = Some(IngressBackend {
+            service: Some(IngressServiceBackend {
+                name: String::from("web"),
+                port: Some(ServiceBackendPort {
+                    number: Some(80),
+                    name: None,
+                }),
+            }),
+            resource: None,
+        });
+
+        let backends = parse_ingress_spec(canary, &service_ports, &auth_secrets).unwrap();
+        assert_eq!(backends.rules[0].canary_weight, Some(10));
+        assert!(backends.default.is_none());
+
+        let primary = parse_ingress_spec(
+            ingress("alpha", "web", "alpha.foo.com"),
+            &service_ports,
+            &auth_secrets,
+        )
+        .unwrap();
+        assert_eq!(primary.rules[0].canary_weight, None);
+    }
+
+    #[test]
+    fn test_redirect_hosts() {
+        let service_ports = ServicePorts::default();
+        let auth_secrets = AuthSecrets::default();
+
+        let mut ing = ingress("alpha", "web", "alpha.foo.com");
+        ing.spec.as_mut().unwrap().tls = Some(vec![IngressTLS {
+            hosts: None,
+            secret_name: Some(String::from("alpha-tls")),
+        }]);
+        ing.metadata.annotations = Some(BTreeMap::from([(
+            WWW_REDIRECT_ANNOTATION.to_string(),
+            String::from("true"),
+        )]));
+
+        // A TLS entry without hosts covers the hosts of the rules.
+        let backends = parse_ingress_spec(ing.clone(), &service_ports, &auth_secrets).unwrap();
+        assert_eq!(backends.https_hosts, vec![String::from("alpha.foo.com")]);
+        assert_eq!(backends.www_hosts, vec![String::from("alpha.foo.com")]);
+
+        ing.metadata.annotations = Some(BTreeMap::from([(
+            SSL_REDIRECT_ANNOTATION.to_string(),
+            String::from("false"),
+        )]));
+        let backends = parse_ingress_spec(ing.clone(), &service_ports, &auth_secrets).unwrap();
+        assert!(backends.https_hosts.is_empty());
+        assert!(backends.www_hosts.is_empty());
+
+        ing.metadata.annotations = None;
+        ing.spec.as_mut().unwrap().tls = Some(vec![IngressTLS {
+            hosts: Some(vec![String::from("bad host")]),
+            secret_name: None,
+        }]);
+        assert!(parse_ingress_spec(ing, &service_ports, &auth_secrets).is_err());
+    }
+
+    #[test]
+    fn test_basic_auth_ingress() {
+        let service_ports = ServicePorts::default();
+        let mut auth_secrets = AuthSecrets::default();
+
+        let mut ing = ingress("alpha", "admin", "alpha.foo.com");
+        ing.metadata.annotations = Some(BTreeMap::from([(
+            AUTH_SECRET_ANNOTATION.to_string(),
+            String::from("basic-auth"),
+        )]));
+
+        // The Ingress is not rendered without its Secret.
+        let err = parse_ingress_spec(ing.clone(), &service_ports, &auth_secrets).unwrap_err();
+        assert!(err.contains("alpha/basic-auth"));
+
+        let mut secret = Secret {
+            metadata: ObjectMeta {
+                name: Some(String::from("basic-auth")),
+                namespace: Some(String::from("alpha")),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(
+                AUTH_KEY.to_string(),
+                ByteString(b"alice:secret".to_vec()),
+            )])),
+            ..Default::default()
+        };
+        auth_secrets.apply(&secret);
+
+        let backends = parse_ingress_spec(ing.clone(), &service_ports, &auth_secrets).unwrap();
+        let auth = backends.rules[0].auth.as_ref().unwrap();
+        assert_eq!(
+            auth.credentials,
+            vec![String::from("Basic YWxpY2U6c2VjcmV0")]
+        );
+
+        // A rotated Secret yields new credentials.
+        secret.data = Some(BTreeMap::from([(
+            AUTH_KEY.to_string(),
+            ByteString(b"alice:rotated".to_vec()),
+        )]));
+        assert!(auth_secrets.apply(&secret));
+        let rotated = parse_ingress_spec(ing, &service_ports, &auth_secrets).unwrap();
+        assert_ne!(rotated, backends);
+    }
+
+    #[test]
+    fn test_unreadable_auth_secret() {
+        let mut state = IngressState {
+            classes: varnish_classes(),
+            namespaces: WatchedNamespaces::default(),
+            service_ports: ServicePorts::default(),
+            auth_secrets: AuthSecrets::default(),
+            backends: HashMap::new(),
+            events: Events::default(),
+        };
+        state
+            .auth_secrets
+            .track(BTreeSet::from([String::from("alpha/basic-auth")]));
+
+        let mut admin = ingress("alpha", "admin", "alpha.foo.com");
+        admin.metadata.annotations = Some(BTreeMap::from([(
+            AUTH_SECRET_ANNOTATION.to_string(),
+            String::from("basic-auth"),
+        )]));
+        let web = ingress("beta", "web", "beta.foo.com");
+
+        // The Ingress referencing a Secret which is not listed yet is
+        // left aside, the other ones are reconciled.
+        state.refresh([&admin, &web].into_iter());
+        assert!(state.backends.contains_key("beta/web"));
+        assert!(!state.backends.contains_key("alpha/admin"));
+        assert!(state.events.pending.is_empty());
+
+        // A watcher error, e.g. a forbidden Secret, fails the
+        // referencing Ingress only.
+        assert!(
+            state
+                .auth_secrets
+                .handle("alpha/basic-auth", Err(WatcherError::NoResourceVersion))
+        );
+        state.refresh([&admin, &web].into_iter());
+        assert!(state.backends.contains_key("beta/web"));
+        assert!(!state.backends.contains_key("alpha/admin"));
+        assert_eq!(state.events.pending.len(), 1);
+        assert_eq!(state.events.pending[0].reason, REASON_INVALID_INGRESS);
+        assert!(state.events.pending[0].note.contains("alpha/basic-auth"));
+    }
+}
@@ -6,20 +6,35 @@ use ingress::watch_ingresses;
 use kube::Client;
 use leader::run_leader_election;
 use log::error;
+use namespaces::WatchedNamespaces;
 use service::watch_service;
+use status::IngressStatus;
 use std::process;
 use std::sync::Arc;
 use std::sync::atomic::AtomicBool;
 use std::{cell::RefCell, rc::Rc};
 use tokio::join;
+use tokio::sync::watch;
 use varnish::{Varnish, start};
-use vcl::Vcl;
+use vcl::{Vcl, parse_fallback_backend, parse_probe};
 
+mod annotations;
+mod annotations_test;
 mod cli;
 mod configmap;
+mod events;
 mod ingress;
+mod ingress_class;
+mod ingress_test;
 mod leader;
+mod namespaces;
+mod ports;
+mod ports_test;
+mod secrets;
+mod secrets_test;
 mod service;
+mod status;
+mod status_test;
 mod varnish;
 mod varnishlog;
 mod varnishlog_test;
@@ -66,13 +81,34 @@ async fn main() {
         }
     };
 
-    let vcl = Vcl::new(
+    let fallback_backend = match parse_fallback_backend(&args.default_backend) {
+        Ok(b) => b,
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let default_probe = match parse_probe(&args.default_probe) {
+        Ok(p) => p,
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    };
+
+    let mut vcl = Vcl::new(
         &args.vcl_file,
         &args.template,
         &args.work_folder,
         args.vcl_recv_snippet,
         args.vcl_snippet,
+        fallback_backend,
+        args.normalize_host,
     );
+    vcl.default_probe = default_probe;
+    vcl.director = args.endpoint_backends.then(|| args.director.clone());
+    vcl.digest = args.digest_vmod;
 
     let rc_vcl = Rc::new(RefCell::new(vcl));
 
@@ -80,14 +116,24 @@ async fn main() {
 
     let leader_future = run_leader_election(leader_status.clone(), client.clone());
 
+    let (addresses_tx, addresses_rx) = watch::channel(Vec::new());
+
     let service_future = watch_service(
-        leader_status.clone(),
+        addresses_tx,
         client.clone(),
         "varnish-ingress-service",
         &args.namespace,
     );
-    let ingress_future = watch_ingresses(client.clone(), &rc_vcl, &args.ingress_class);
-    let configmap_future = watch_configmap(client, &rc_vcl, &args.namespace);
+    let ingress_future = watch_ingresses(
+        client.clone(),
+        &rc_vcl,
+        &args.ingress_class,
+        &args.controller_name,
+        args.ingress_label_filter,
+        WatchedNamespaces::new(&args.watch_namespace, &args.namespace_selector),
+        IngressStatus::new(client.clone(), leader_status, addresses_rx),
+    );
+    let configmap_future = watch_configmap(client, &rc_vcl, &args.namespace, &args.controller_name);
 
     let (leader_result, service_result, ingress_result, configmap_result) = join!(
         leader_future,
@@ -0,0 +1,137 @@
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+use k8s_openapi::NamespaceResourceScope;
+use k8s_openapi::api::core::v1::Namespace;
+use kube::runtime::watcher::Error as WatcherError;
+use kube::{
+    Api, Client, Resource,
+    runtime::{WatchStreamExt, watcher},
+};
+use serde::de::DeserializeOwned;
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+
+///
+/// WatchedNamespaces restricts the controller to a set of namespaces.
+///
+/// The namespaces are either listed explicitly, in which case
+/// one watcher is started per namespace and the controller may run
+/// under a namespaced Role, or selected by their labels.
+///
+/// Both restrictions may be combined, when neither is
+/// configured all the namespaces are watched.
+#[derive(Debug, Default)]
+pub struct WatchedNamespaces {
+    /// Namespaces listed via --watch-namespace.
+    listed: BTreeSet<String>,
+
+    /// Label selector set via --namespace-selector.
+    selector: Option<String>,
+
+    /// Namespaces currently matching the label selector.
+    selected: BTreeSet<String>,
+}
+
+impl WatchedNamespaces {
+    pub fn new(listed: &[String], selector: &str) -> Self {
+        WatchedNamespaces {
+            listed: listed
+                .iter()
+                .filter(|namespace| !namespace.is_empty())
+                .cloned()
+                .collect(),
+            selector: (!selector.is_empty()).then(|| selector.to_string()),
+            selected: BTreeSet::new(),
+        }
+    }
+
+    /// Whether the namespace is watched by the controller.
+    pub fn contains(&self, namespace: &str) -> bool {
+        (self.listed.is_empty() || self.listed.contains(namespace))
+            && (self.selector.is_none() || self.selected.contains(namespace))
+    }
+
+    ///
+    /// Watch the resources of the listed namespaces,
+    /// or of all the namespaces if none were listed.
+    ///
+    pub fn watch<K>(
+        &self,
+        client: &Client,
+        config: watcher::Config,
+    ) -> BoxStream<'static, Result<watcher::Event<K>, WatcherError>>
+    where
+        K: Resource<Scope = NamespaceResourceScope>
+            + Clone
+            + DeserializeOwned
+            + Debug
+            + Send
+            + 'static,
+        K::DynamicType: Default,
+    {
+        if self.listed.is_empty() {
+            return watcher(Api::<K>::all(client.clone()), config)
+                .default_backoff()
+                .boxed();
+        }
+
+        stream::select_all(self.listed.iter().map(|namespace| {
+            watcher(
+                Api::<K>::namespaced(client.clone(), namespace),
+                config.clone(),
+            )
+            .default_backoff()
+            .boxed()
+        }))
+        .boxed()
+    }
+
+    /// The number of watchers started by [WatchedNamespaces::watch].
+    pub fn watchers(&self) -> usize {
+        self.listed.len().max(1)
+    }
+
+    ///
+    /// Watch the namespaces matching the label selector,
+    /// a stream which never yields if there is no selector.
+    ///
+    pub fn watch_selected(
+        &self,
+        client: &Client,
+    ) -> BoxStream<'static, Result<watcher::Event<Namespace>, WatcherError>> {
+        match &self.selector {
+            Some(selector) => watcher(
+                Api::<Namespace>::all(client.clone()),
+                watcher::Config::default().labels(selector),
+            )
+            .default_backoff()
+            .boxed(),
+            None => stream::pending().boxed(),
+        }
+    }
+
+    ///
+    /// Mark the namespace as selected.
+    ///
+    /// Returns true when the selected namespaces changed.
+    ///
+    pub fn select(&mut self, namespace: &Namespace) -> bool {
+        self.selected
+            .insert(namespace.metadata.name.clone().unwrap_or_default())
+    }
+
+    ///
+    /// Mark the namespace as no longer selected.
+    ///
+    /// Returns true when the selected namespaces changed.
+    ///
+    pub fn deselect(&mut self, namespace: &Namespace) -> bool {
+        self.selected
+            .remove(namespace.metadata.name.as_deref().unwrap_or_default())
+    }
+
+    /// Whether the namespaces are selected by labels.
+    pub fn has_selector(&self) -> bool {
+        self.selector.is_some()
+    }
+}
@@ -0,0 +1,265 @@
+use crate::vcl::Endpoint;
+use k8s_openapi::api::core::v1::Service;
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+use k8s_openapi::api::networking::v1::IngressServiceBackend;
+use std::collections::{BTreeSet, HashMap};
+
+/// The label linking an EndpointSlice to its service.
+pub const SERVICE_NAME_LABEL: &str = "kubernetes.io/service-name";
+
+///
+/// ServicePorts keeps track of the named ports exposed
+/// by the Kubernetes services in the cluster, and of the
+/// ready endpoints behind them.
+///
+/// Ingress backends may reference a service port either
+/// by its number or by its name, Varnish however needs
+/// the actual number in its backend definition.
+///
+/// https://kubernetes.io/docs/reference/kubernetes-api/service-resources/ingress-v1/#IngressServiceBackend
+#[derive(Debug, Default)]
+pub struct ServicePorts {
+    /// Ports keyed by <namespace>/<service-name>, then by their
+    /// name, which is empty for the single port of a service.
+    ports: HashMap<String, HashMap<String, i32>>,
+
+    /// Endpoints keyed by <namespace>/<service-name>,
+    /// then by the name of their EndpointSlice.
+    endpoints: HashMap<String, HashMap<String, SliceEndpoints>>,
+
+    /// The services whose endpoints are stored, <namespace>/<service-name>,
+    /// all of them until the referenced services are tracked.
+    referenced: Option<BTreeSet<String>>,
+}
+
+/// The ready endpoints listed in an EndpointSlice.
+#[derive(Debug, Default, Clone, PartialEq)]
+struct SliceEndpoints {
+    /// Target ports keyed by the name of the service port.
+    ports: HashMap<String, i32>,
+    addresses: Vec<String>,
+}
+
+impl ServicePorts {
+    ///
+    /// Store the ports of the provided service.
+    ///
+    /// Returns true when the ports differ from
+    /// the ones previously known for this service.
+    ///
+    pub fn apply(&mut self, svc: &Service) -> bool {
+        let named_ports: HashMap<String, i32> = svc
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.ports.as_ref())
+            .into_iter()
+            .flatten()
+            .map(|p| (p.name.clone().unwrap_or_default(), p.port))
+            .collect();
+
+        self.ports.insert(service_key(svc), named_ports.clone()) != Some(named_ports)
+    }
+
+    ///
+    /// Forget the ports of the provided service.
+    ///
+    /// Returns true when the service was previously known.
+    ///
+    pub fn delete(&mut self, svc: &Service) -> bool {
+        self.ports.remove(&service_key(svc)).is_some()
+    }
+
+    ///
+    /// Resolve the port number of an Ingress service backend,
+    /// looking up the service's named ports when needed.
+    ///
+    pub fn resolve(
+        &self,
+        namespace: &str,
+        backend_service: &IngressServiceBackend,
+    ) -> Result<u16, String> {
+        let port = backend_service.port.as_ref().ok_or(format!(
+            "Port of service [{}] is missing",
+            backend_service.name
+        ))?;
+
+        if let Some(number) = port.number {
+            return Ok(number as u16);
+        }
+
+        let name = port.name.as_deref().ok_or(format!(
+            "Port of service [{}] has neither a number nor a name",
+            backend_service.name
+        ))?;
+
+        self.ports
+            .get(&format!("{namespace}/{}", backend_service.name))
+            .and_then(|ports| ports.get(name))
+            .map(|number| *number as u16)
+            .ok_or(format!(
+                "Port [{name}] not found on service [{}] in namespace [{namespace}]",
+                backend_service.name
+            ))
+    }
+
+    ///
+    /// Store the ready endpoints of the provided EndpointSlice.
+    ///
+    /// Returns true when the endpoints differ from the
+    /// ones previously known for this EndpointSlice.
+    ///
+    pub fn apply_slice(&mut self, slice: &EndpointSlice) -> bool {
+        let Some(key) = slice_service_key(slice) else {
+            return false;
+        };
+
+        if self
+            .referenced
+            .as_ref()
+            .is_some_and(|referenced| !referenced.contains(&key))
+        {
+            return false;
+        }
+
+        let ports = slice
+            .ports
+            .iter()
+            .flatten()
+            .filter_map(|p| {
+                p.port
+                    .map(|port| (p.name.clone().unwrap_or_default(), port))
+            })
+            .collect();
+
+        // Endpoints with an unknown readiness are considered ready,
+        // the first address of an endpoint is the one to use.
+        let mut addresses: Vec<String> = slice
+            .endpoints
+            .iter()
+            .filter(|e| e.conditions.as_ref().and_then(|c| c.ready) != Some(false))
+            .filter_map(|e| e.addresses.first().cloned())
+            .collect();
+        addresses.sort();
+        addresses.dedup();
+
+        let endpoints = SliceEndpoints { ports, addresses };
+
+        self.endpoints
+            .entry(key)
+            .or_default()
+            .insert(slice_name(slice), endpoints.clone())
+            != Some(endpoints)
+    }
+
+    ///
+    /// Forget the endpoints of the provided EndpointSlice.
+    ///
+    /// Returns true when the EndpointSlice was previously known.
+    ///
+    pub fn delete_slice(&mut self, slice: &EndpointSlice) -> bool {
+        let Some(key) = slice_service_key(slice) else {
+            return false;
+        };
+
+        let Some(slices) = self.endpoints.get_mut(&key) else {
+            return false;
+        };
+
+        let deleted = slices.remove(&slice_name(slice)).is_some();
+        if slices.is_empty() {
+            self.endpoints.remove(&key);
+        }
+
+        deleted
+    }
+
+    ///
+    /// Track the services referenced by the Ingresses, <namespace>/<service-name>,
+    /// the endpoints of the other services are dropped.
+    ///
+    /// Returns true when services which were not tracked are referenced,
+    /// their EndpointSlices must then be listed again.
+    ///
+    pub fn track_services(&mut self, referenced: BTreeSet<String>) -> bool {
+        let added = self
+            .referenced
+            .as_ref()
+            .is_none_or(|tracked| !referenced.is_subset(tracked));
+
+        self.endpoints.retain(|key, _| referenced.contains(key));
+        self.referenced = Some(referenced);
+
+        added
+    }
+
+    ///
+    /// The ready endpoints behind a service port, sorted so
+    /// that the same set of endpoints always yields the same VCL.
+    ///
+    /// The service port is matched by name with the ports of the
+    /// EndpointSlices, which hold the target port of the pods.
+    ///
+    pub fn endpoints(&self, namespace: &str, service: &str, port: u16) -> Vec<Endpoint> {
+        let key = format!("{namespace}/{service}");
+
+        let Some(port_name) = self.ports.get(&key).and_then(|ports| {
+            ports
+                .iter()
+                .find(|(_, number)| **number == i32::from(port))
+                .map(|(name, _)| name)
+        }) else {
+            return vec![];
+        };
+
+        let mut endpoints: Vec<Endpoint> = self
+            .endpoints
+            .get(&key)
+            .into_iter()
+            .flat_map(|slices| slices.values())
+            .filter_map(|slice| {
+                slice
+                    .ports
+                    .get(port_name)
+                    .map(|target_port| (slice, *target_port as u16))
+            })
+            .flat_map(|(slice, target_port)| {
+                slice
+                    .addresses
+                    .iter()
+                    .map(move |address| Endpoint::new(address.clone(), target_port))
+            })
+            .collect();
+        endpoints.sort();
+        endpoints.dedup();
+
+        endpoints
+    }
+}
+
+///
+/// The identity of a service, <namespace>/<name>.
+///
+pub fn service_key(svc: &Service) -> String {
+    format!(
+        "{}/{}",
+        svc.metadata.namespace.as_deref().unwrap_or("default"),
+        svc.metadata.name.as_deref().unwrap_or_default()
+    )
+}
+
+///
+/// The identity of the service owning an EndpointSlice,
+/// none for the slices not managed for a service.
+///
+pub fn slice_service_key(slice: &EndpointSlice) -> Option<String> {
+    let service = slice.metadata.labels.as_ref()?.get(SERVICE_NAME_LABEL)?;
+
+    Some(format!(
+        "{}/{service}",
+        slice.metadata.namespace.as_deref().unwrap_or("default")
+    ))
+}
+
+fn slice_name(slice: &EndpointSlice) -> String {
+    slice.metadata.name.clone().unwrap_or_default()
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod test {
+    use crate::ports::ServicePorts;
+    use crate::vcl::Endpoint;
+    use k8s_openapi::api::core::v1::{Service, ServicePort, ServiceSpec};
+    use k8s_openapi::api::discovery::v1::{
+        Endpoint as SliceEndpoint, EndpointConditions, EndpointPort, EndpointSlice,
+    };
+    use k8s_openapi::api::networking::v1::{IngressServiceBackend, ServiceBackendPort};
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    fn service(name: &str, ports: Vec<(&str, i32)>) -> Service {
+        Service {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(String::from("foo")),
+                ..Default::default()
+            },
+            spec: Some(ServiceSpec {
+                ports: Some(
+                    ports
+                        .into_iter()
+                        .map(|(name, port)| ServicePort {
+                            name: Some(name.to_string()),
+                            port,
+                            ..Default::default()
+                        })
+                        .collect(),
+                ),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn backend(name: &str, port: ServiceBackendPort) -> IngressServiceBackend {
+        IngressServiceBackend {
+            name: name.to_string(),
+            port: Some(port),
+        }
+    }
+
+    #[test]
+    fn test_resolve_ports() {
+        let mut ports = ServicePorts::default();
+
+        assert!(ports.apply(&service("web", vec![("http", 8080), ("metrics", 9090)])));
+        assert!(!ports.apply(&service("web", vec![("http", 8080), ("metrics", 9090)])));
+
+        let numbered = backend(
+            "web",
+            ServiceBackendPort {
+                number: Some(80),
+                name: None,
+            },
+        );
+        let named = backend(
+            "web",
+            ServiceBackendPort {
+                number: None,
+                name: Some(String::from("http")),
+            },
+        );
+
+        assert_eq!(ports.resolve("foo", &numbered), Ok(80));
+        assert_eq!(ports.resolve("foo", &named), Ok(8080));
+        assert!(ports.resolve("bar", &named).is_err());
+
+        assert!(ports.apply(&service("web", vec![("http", 8081)])));
+        assert_eq!(ports.resolve("foo", &named), Ok(8081));
+
+        assert!(ports.delete(&service("web", vec![])));
+        assert!(ports.resolve("foo", &named).is_err());
+    }
+
+    fn slice(name: &str, port: (&str, i32), endpoints: Vec<(&str, Option<bool>)>) -> EndpointSlice {
+        EndpointSlice {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(String::from("foo")),
+                labels: Some(BTreeMap::from([(
+                    String::from("kubernetes.io/service-name"),
+                    String::from("web"),
+                )])),
+                ..Default::default()
+            },
+            address_type: String::from("IPv4"),
+            ports: Some(vec![EndpointPort {
+                name: Some(port.0.to_string()),
+                port: Some(port.1),
+                ..Default::default()
+            }]),
+            endpoints: endpoints
+                .into_iter()
+                .map(|(address, ready)| SliceEndpoint {
+                    addresses: vec![address.to_string()],
+                    conditions: Some(EndpointConditions {
+                        ready,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut ports = ServicePorts::default();
+        assert!(ports.apply(&service("web", vec![("http", 80), ("metrics", 9090)])));
+
+        assert!(ports.apply_slice(&slice(
+            "web-a",
+            ("http", 8080),
+            vec![("10.0.0.2", Some(true)), ("10.0.0.3", Some(false))],
+        )));
+        assert!(ports.apply_slice(&slice("web-b", ("http", 8080), vec![("10.0.0.1", None)])));
+        assert!(!ports.apply_slice(&slice("web-b", ("http", 8080), vec![("10.0.0.1", None)])));
+
+        assert_eq!(
+            ports.endpoints("foo", "web", 80),
+            vec![
+                Endpoint::new(String::from("10.0.0.1"), 8080),
+                Endpoint::new(String::from("10.0.0.2"), 8080),
+            ]
+        );
+        assert!(ports.endpoints("foo", "web", 9090).is_empty());
+        assert!(ports.endpoints("foo", "web", 81).is_empty());
+        assert!(ports.endpoints("bar", "web", 80).is_empty());
+
+        assert!(ports.delete_slice(&slice("web-a", ("http", 8080), vec![])));
+        assert!(!ports.delete_slice(&slice("web-a", ("http", 8080), vec![])));
+        assert_eq!(
+            ports.endpoints("foo", "web", 80),
+            vec![Endpoint::new(String::from("10.0.0.1"), 8080)]
+        );
+    }
+
+    #[test]
+    fn test_track_services() {
+        let mut ports = ServicePorts::default();
+        ports.apply(&service("web", vec![("http", 80)]));
+
+        // The slices of the services which are not referenced are not stored.
+        assert!(ports.track_services(BTreeSet::from([String::from("foo/api")])));
+        assert!(!ports.apply_slice(&slice("web-a", ("http", 8080), vec![("10.0.0.1", None)])));
+        assert!(ports.endpoints("foo", "web", 80).is_empty());
+
+        // Newly referenced services must be listed again.
+        assert!(ports.track_services(BTreeSet::from([
+            String::from("foo/api"),
+            String::from("foo/web"),
+        ])));
+        assert!(ports.apply_slice(&slice("web-a", ("http", 8080), vec![("10.0.0.1", None)])));
+        assert_eq!(
+            ports.endpoints("foo", "web", 80),
+            vec![Endpoint::new(String::from("10.0.0.1"), 8080)]
+        );
+
+        // The endpoints of the services no longer referenced are dropped.
+        assert!(!ports.track_services(BTreeSet::from([String::from("foo/api")])));
+        assert!(ports.endpoints("foo", "web", 80).is_empty());
+    }
+}
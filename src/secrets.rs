@@ -0,0 +1,328 @@
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures::StreamExt;
+use futures::stream::{self, BoxStream};
+use k8s_openapi::api::core::v1::Secret;
+use kube::runtime::watcher::Error as WatcherError;
+use kube::{
+    Api, Client,
+    runtime::{WatchStreamExt, watcher},
+};
+use log::error;
+use std::collections::{BTreeSet, HashMap};
+
+/// The key of the credentials in the basic auth Secrets.
+pub const AUTH_KEY: &str = "auth";
+
+/// The prefix of the SHA-1 htpasswd entries, verified with the digest vmod.
+const SHA1_PREFIX: &str = "{SHA}";
+
+/// The htpasswd hash formats, which Varnish can not verify.
+const HASH_PREFIXES: [&str; 7] = ["$apr1$", "$2a$", "$2b$", "$2y$", "$1$", "$5$", "$6$"];
+
+///
+/// The credentials held by a basic auth Secret.
+///
+#[derive(Clone, Default, PartialEq)]
+pub struct Credentials {
+    /// The Authorization header values of the plain text entries.
+    pub headers: Vec<String>,
+
+    /// The `user:<hex SHA-1 of the password>` values of the `{SHA}` entries.
+    pub sha1: Vec<String>,
+}
+
+/// The credentials are kept out of the logs.
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("headers", &self.headers.len())
+            .field("sha1", &self.sha1.len())
+            .finish()
+    }
+}
+
+/// The events of the Secret watchers, keyed by <namespace>/<secret-name>.
+pub type SecretEvents = BoxStream<'static, (String, Result<watcher::Event<Secret>, WatcherError>)>;
+
+///
+/// AuthSecrets keeps track of the credentials held by the
+/// Kubernetes Secrets referenced by the basic auth annotation.
+///
+/// The Secrets hold `user:password` lines under their `auth` key.
+/// The passwords are in plain text, or SHA-1 htpasswd entries when
+/// the digest vmod is imported. The other hashes are rejected since
+/// VCL can not compute them.
+///
+/// Only the referenced Secrets are watched, one by one, so that
+/// neither the controller memory nor its RBAC cover the others.
+///
+/// Neither the credentials nor the Secret contents are ever logged,
+/// which is why this type does not implement Debug.
+#[derive(Default)]
+pub struct AuthSecrets {
+    /// Authorization header values keyed by <namespace>/<secret-name>,
+    /// or the reason why the Secret can not be used.
+    credentials: HashMap<String, Result<Credentials, String>>,
+
+    /// The watched Secrets, <namespace>/<secret-name>.
+    watched: BTreeSet<String>,
+
+    /// The watched Secrets which were not listed yet.
+    pending: BTreeSet<String>,
+
+    /// Whether the `{SHA}` entries are accepted.
+    sha1: bool,
+}
+
+impl AuthSecrets {
+    ///
+    /// Track the basic auth Secrets, `sha1` accepts the `{SHA}`
+    /// entries, which require the digest vmod.
+    ///
+    pub fn new(sha1: bool) -> Self {
+        AuthSecrets {
+            sha1,
+            ..Default::default()
+        }
+    }
+
+    ///
+    /// Store the credentials of the provided Secret, the Secrets
+    /// without an `auth` key are ignored.
+    ///
+    /// Returns true when the credentials differ from
+    /// the ones previously known for this Secret.
+    ///
+    pub fn apply(&mut self, secret: &Secret) -> bool {
+        let Some(auth) = secret.data.as_ref().and_then(|data| data.get(AUTH_KEY)) else {
+            return self.delete(secret);
+        };
+
+        let credentials = parse_credentials(&auth.0, self.sha1);
+
+        self.credentials
+            .insert(secret_key(secret), credentials.clone())
+            != Some(credentials)
+    }
+
+    ///
+    /// Forget the credentials of the provided Secret.
+    ///
+    /// Returns true when the Secret was previously known.
+    ///
+    pub fn delete(&mut self, secret: &Secret) -> bool {
+        self.credentials.remove(&secret_key(secret)).is_some()
+    }
+
+    ///
+    /// Watch the provided Secrets, <namespace>/<secret-name>,
+    /// when they differ from the watched ones.
+    ///
+    /// Returns the stream of the new watchers, whose events are keyed
+    /// by their Secret, a stream which never yields when no Secret is
+    /// referenced. The Secrets are pending until they are listed again.
+    ///
+    pub fn watch(&mut self, client: &Client, referenced: BTreeSet<String>) -> Option<SecretEvents> {
+        if !self.track(referenced) {
+            return None;
+        }
+
+        if self.watched.is_empty() {
+            return Some(stream::pending().boxed());
+        }
+
+        Some(
+            stream::select_all(self.watched.iter().filter_map(|key| {
+                let (namespace, name) = key.split_once('/')?;
+                let key = key.clone();
+                Some(
+                    watcher(
+                        Api::<Secret>::namespaced(client.clone(), namespace),
+                        watcher::Config::default().fields(&format!("metadata.name={name}")),
+                    )
+                    .default_backoff()
+                    .map(move |ev| (key.clone(), ev))
+                    .boxed(),
+                )
+            }))
+            .boxed(),
+        )
+    }
+
+    ///
+    /// Track the provided Secrets, <namespace>/<secret-name>.
+    ///
+    /// Returns true when they differ from the tracked ones, the known
+    /// credentials are then dropped and the Secrets are pending.
+    ///
+    pub fn track(&mut self, referenced: BTreeSet<String>) -> bool {
+        if referenced == self.watched {
+            return false;
+        }
+
+        self.credentials.clear();
+        self.pending = referenced.clone();
+        self.watched = referenced;
+        true
+    }
+
+    ///
+    /// Handle an event of the watcher of the Secret <namespace>/<secret-name>.
+    ///
+    /// A watcher error during the listing, e.g. when the RBAC does not
+    /// allow reading the Secret, counts as a listing without credentials,
+    /// so that only the Ingresses referencing it fail to parse.
+    ///
+    /// Returns true when the Ingresses referencing the Secret must be re-parsed.
+    ///
+    pub fn handle(&mut self, key: &str, ev: Result<watcher::Event<Secret>, WatcherError>) -> bool {
+        match ev {
+            Ok(watcher::Event::Apply(secret)) => self.apply(&secret),
+            Ok(watcher::Event::Delete(secret)) => self.delete(&secret),
+            Ok(watcher::Event::Init) => {
+                self.credentials.remove(key);
+                self.pending.insert(key.to_string());
+                false
+            }
+            Ok(watcher::Event::InitApply(secret)) => {
+                self.apply(&secret);
+                false
+            }
+            Ok(watcher::Event::InitDone) => self.pending.remove(key),
+            Err(e) => {
+                error!("Secret [{key}] watcher error: {e}");
+                if !self.pending.remove(key) {
+                    return false;
+                }
+                self.credentials
+                    .insert(key.to_string(), Err(format!("can not be read: {e}")));
+                true
+            }
+        }
+    }
+
+    ///
+    /// Whether the Secret <namespace>/<name> was not listed yet,
+    /// its credentials are then unknown.
+    ///
+    pub fn is_pending(&self, namespace: &str, name: &str) -> bool {
+        self.pending.contains(&format!("{namespace}/{name}"))
+    }
+
+    /// The number of watchers started by [AuthSecrets::watch].
+    pub fn watchers(&self) -> usize {
+        self.watched.len()
+    }
+
+    ///
+    /// The credentials accepted by the Secret <namespace>/<name>,
+    /// sorted so that the VCL is stable.
+    ///
+    pub fn credentials(&self, namespace: &str, name: &str) -> Result<Credentials, String> {
+        let key = format!("{namespace}/{name}");
+
+        match self.credentials.get(&key) {
+            Some(Ok(credentials)) => Ok(credentials.clone()),
+            Some(Err(e)) => Err(format!("Secret [{key}]: {e}")),
+            None => Err(format!(
+                "Secret [{key}] not found, or it has no [{AUTH_KEY}] key"
+            )),
+        }
+    }
+}
+
+///
+/// Translate the `user:password` lines of a basic auth Secret into
+/// the Authorization header values of the Basic scheme, or into the
+/// `user:<hex SHA-1>` values of the `{SHA}` entries when `sha1` is set.
+///
+/// The errors never quote the lines, which hold the passwords.
+///
+pub fn parse_credentials(auth: &[u8], sha1: bool) -> Result<Credentials, String> {
+    let auth =
+        std::str::from_utf8(auth).map_err(|_| String::from("Invalid UTF-8 in credentials"))?;
+
+    let mut credentials = Credentials::default();
+
+    for (index, line) in auth.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some((user, password)) = line.split_once(':') else {
+            return Err(format!(
+                "Line {} of the credentials is not user:password",
+                index + 1
+            ));
+        };
+
+        if user.is_empty() || password.is_empty() {
+            return Err(format!(
+                "Line {} of the credentials has an empty user or password",
+                index + 1
+            ));
+        }
+
+        if let Some(hash) = password.strip_prefix(SHA1_PREFIX) {
+            if !sha1 {
+                return Err(format!(
+                    "Line {} of the credentials holds a {SHA1_PREFIX} password, which requires the digest vmod",
+                    index + 1
+                ));
+            }
+
+            let hash = STANDARD
+                .decode(hash)
+                .ok()
+                .filter(|hash| hash.len() == 20)
+                .ok_or_else(|| {
+                    format!(
+                        "Line {} of the credentials holds an invalid {SHA1_PREFIX} password",
+                        index + 1
+                    )
+                })?;
+
+            let hex: String = hash.iter().map(|byte| format!("{byte:02x}")).collect();
+            credentials.sha1.push(format!("{user}:{hex}"));
+            continue;
+        }
+
+        if HASH_PREFIXES
+            .iter()
+            .any(|prefix| password.starts_with(prefix))
+        {
+            return Err(format!(
+                "Line {} of the credentials holds a hashed password, only plain text and {SHA1_PREFIX} passwords are supported",
+                index + 1
+            ));
+        }
+
+        credentials
+            .headers
+            .push(format!("Basic {}", STANDARD.encode(line)));
+    }
+
+    if credentials.headers.is_empty() && credentials.sha1.is_empty() {
+        return Err(String::from("The Secret holds no credentials"));
+    }
+
+    credentials.headers.sort();
+    credentials.headers.dedup();
+    credentials.sha1.sort();
+    credentials.sha1.dedup();
+
+    Ok(credentials)
+}
+
+///
+/// The identity of a Secret, <namespace>/<name>.
+///
+pub fn secret_key(secret: &Secret) -> String {
+    format!(
+        "{}/{}",
+        secret.metadata.namespace.as_deref().unwrap_or("default"),
+        secret.metadata.name.as_deref().unwrap_or_default()
+    )
+}
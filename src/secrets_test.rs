@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod test {
+    use crate::secrets::{AUTH_KEY, AuthSecrets, Credentials, parse_credentials};
+    use k8s_openapi::ByteString;
+    use k8s_openapi::api::core::v1::Secret;
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+    use std::collections::BTreeMap;
+
+    fn secret(name: &str, key: &str, auth: &str) -> Secret {
+        Secret {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                namespace: Some(String::from("foo")),
+                ..Default::default()
+            },
+            data: Some(BTreeMap::from([(
+                key.to_string(),
+                ByteString(auth.as_bytes().to_vec()),
+            )])),
+            ..Default::default()
+        }
+    }
+
+    fn headers(headers: &[&str]) -> Credentials {
+        Credentials {
+            headers: headers.iter().map(|h| h.to_string()).collect(),
+            sha1: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_credentials() {
+        // echo -n 'alice:secret' | base64
+        assert_eq!(
+            parse_credentials(b"bob:hunter2\n\nalice:secret\n", false),
+            Ok(headers(&[
+                "Basic YWxpY2U6c2VjcmV0",
+                "Basic Ym9iOmh1bnRlcjI="
+            ]))
+        );
+        assert_eq!(
+            parse_credentials(b"alice:pass:word", false),
+            Ok(headers(&["Basic YWxpY2U6cGFzczp3b3Jk"]))
+        );
+
+        assert!(parse_credentials(b"", false).is_err());
+        assert!(parse_credentials(b"alice", false).is_err());
+        assert!(parse_credentials(b"alice:", false).is_err());
+        assert!(parse_credentials(b"alice:$apr1$abc$def", true).is_err());
+        assert!(parse_credentials(&[0xff, b':', b'a'], false).is_err());
+
+        // The errors never quote the passwords.
+        let err = parse_credentials(b"alice:secret\nbob", false).unwrap_err();
+        assert!(err.contains("Line 2"));
+        assert!(!err.contains("secret"));
+    }
+
+    #[test]
+    fn test_parse_sha1_credentials() {
+        // htpasswd -nbs alice password
+        let htpasswd = b"alice:{SHA}W6ph5Mm5Pz8GgiULbPgzG37mj9g=\nbob:hunter2";
+
+        // The {SHA} entries require the digest vmod.
+        let err = parse_credentials(htpasswd, false).unwrap_err();
+        assert!(err.contains("Line 1"));
+        assert!(err.contains("digest"));
+
+        // echo -n 'password' | sha1sum
+        assert_eq!(
+            parse_credentials(htpasswd, true),
+            Ok(Credentials {
+                headers: vec![String::from("Basic Ym9iOmh1bnRlcjI=")],
+                sha1: vec![String::from(
+                    "alice:5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8"
+                )],
+            })
+        );
+
+        assert!(parse_credentials(b"alice:{SHA}", true).is_err());
+        assert!(parse_credentials(b"alice:{SHA}not-base64", true).is_err());
+        assert!(parse_credentials(b"alice:{SHA}YWxpY2U=", true).is_err());
+    }
+
+    #[test]
+    fn test_auth_secrets() {
+        let mut secrets = AuthSecrets::default();
+
+        assert!(secrets.apply(&secret("basic-auth", AUTH_KEY, "alice:secret")));
+        assert!(!secrets.apply(&secret("basic-auth", AUTH_KEY, "alice:secret")));
+        assert_eq!(
+            secrets.credentials("foo", "basic-auth"),
+            Ok(headers(&["Basic YWxpY2U6c2VjcmV0"]))
+        );
+
+        // A rotated Secret is a change.
+        assert!(secrets.apply(&secret("basic-auth", AUTH_KEY, "alice:rotated")));
+        assert!(secrets.credentials("bar", "basic-auth").is_err());
+
+        // So is an invalid one, which can not be used.
+        assert!(secrets.apply(&secret("basic-auth", AUTH_KEY, "alice:$2y$05$abc")));
+        let err = secrets.credentials("foo", "basic-auth").unwrap_err();
+        assert!(err.contains("foo/basic-auth"));
+
+        // Secrets without credentials are not tracked.
+        assert!(!secrets.apply(&secret("tls", "tls.crt", "")));
+        assert!(secrets.apply(&secret("basic-auth", "tls.crt", "")));
+        assert!(secrets.credentials("foo", "basic-auth").is_err());
+
+        secrets.apply(&secret("basic-auth", AUTH_KEY, "alice:secret"));
+        assert!(secrets.delete(&secret("basic-auth", AUTH_KEY, "")));
+        assert!(!secrets.delete(&secret("basic-auth", AUTH_KEY, "")));
+    }
+}
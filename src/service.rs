@@ -1,18 +1,16 @@
 use futures::{StreamExt, TryStreamExt};
-use k8s_openapi::api::core::v1::Service;
-use k8s_openapi::api::networking::v1::IngressLoadBalancerIngress;
+use k8s_openapi::api::core::v1::{PortStatus, Service, ServiceSpec};
+use k8s_openapi::api::networking::v1::{IngressLoadBalancerIngress, IngressPortStatus};
 use std::cmp::Ordering;
 use std::collections::HashSet;
 
-use crate::ingress::update_status;
 use kube::runtime::watcher::Error as WatcherError;
 use kube::{
     Api, Client,
     runtime::{WatchStreamExt, watcher},
 };
 use log::{error, info};
-use std::sync::Arc;
-use std::sync::atomic::AtomicBool;
+use tokio::sync::watch;
 
 const POD_LABELS: &str = "app=varnish-ingress-controller";
 const SVC_EXTERNAL_NAME: &str = "ExternalName";
@@ -20,8 +18,12 @@ const SVC_CLUSTER_IP: &str = "ClusterIP";
 const SVC_NODE_PORT: &str = "NodePort";
 const SVC_LOAD_BALANCER: &str = "LoadBalancer";
 
+///
+/// Watch the controller Service and publish its addresses,
+/// the Ingress watcher advertises them in the Ingress status.
+///
 pub async fn watch_service(
-    leader_status: Arc<AtomicBool>,
+    addresses: watch::Sender<Vec<IngressLoadBalancerIngress>>,
     client: Client,
     name: &str,
     namespace: &str,
@@ -37,18 +39,18 @@ pub async fn watch_service(
     );
 
     while let Some(sv) = observer.try_next().await.unwrap() {
-        if !leader_status.load(std::sync::atomic::Ordering::Relaxed) {
-            continue;
-        }
-
-        if let watcher::Event::Apply(svc) = sv {
+        if let watcher::Event::Apply(svc) | watcher::Event::InitApply(svc) = sv {
             match update_status_from_svc(svc).await {
                 Ok(mut lbi) => {
                     info!("reading service [{name}]");
                     lbi = sort_load_balancer_ingresses(lbi);
-                    if let Err(e) = update_status(client.clone(), lbi).await {
-                        error!("Failed updating ingress status: {e}");
-                    }
+                    addresses.send_if_modified(|current| {
+                        if *current == lbi {
+                            return false;
+                        }
+                        *current = lbi;
+                        true
+                    });
                 }
                 Err(e) => {
                     error!("{e}");
@@ -63,6 +65,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
     let spec = svc.spec.as_ref().ok_or("Service spec not found")?;
 
     let svc_type = spec.type_.as_deref();
+    let ports = service_port_status(spec);
 
     match svc_type {
         Some(SVC_EXTERNAL_NAME) => {
@@ -75,7 +78,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
             Ok(vec![IngressLoadBalancerIngress {
                 hostname: Some(external_name.clone()),
                 ip: None,
-                ports: None,
+                ports: ports.clone(),
             }])
         }
 
@@ -86,7 +89,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
             Ok(vec![IngressLoadBalancerIngress {
                 ip: Some(cluster_ip.clone()),
                 hostname: None,
-                ports: None,
+                ports: ports.clone(),
             }])
         }
 
@@ -100,7 +103,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
                 return Ok(vec![IngressLoadBalancerIngress {
                     ip: Some(cluster_ip.clone()),
                     hostname: None,
-                    ports: None,
+                    ports: ports.clone(),
                 }]);
             }
 
@@ -109,7 +112,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
                 .map(|ip| IngressLoadBalancerIngress {
                     ip: Some(ip.clone()),
                     hostname: None,
-                    ports: None,
+                    ports: ports.clone(),
                 })
                 .collect();
 
@@ -128,7 +131,8 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
                         addrs.extend(ingresses.iter().map(|ingress| IngressLoadBalancerIngress {
                             ip: ingress.ip.clone(),
                             hostname: ingress.hostname.clone(),
-                            ports: None,
+                            ports: load_balancer_port_status(ingress.ports.as_deref())
+                                .or_else(|| ports.clone()),
                         }));
                     }
 
@@ -139,7 +143,7 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
                     addrs.push(IngressLoadBalancerIngress {
                         ip: Some(ip.clone()),
                         hostname: None,
-                        ports: None,
+                        ports: ports.clone(),
                     });
                 }
             }
@@ -153,6 +157,46 @@ async fn update_status_from_svc(svc: Service) -> Result<Vec<IngressLoadBalancerI
     }
 }
 
+///
+/// The ports exposed by the Service, as advertised
+/// in the Ingress load balancer status.
+///
+fn service_port_status(spec: &ServiceSpec) -> Option<Vec<IngressPortStatus>> {
+    let ports: Vec<IngressPortStatus> = spec
+        .ports
+        .iter()
+        .flatten()
+        .map(|p| IngressPortStatus {
+            port: p.port,
+            protocol: p.protocol.clone().unwrap_or_else(|| String::from("TCP")),
+            error: None,
+        })
+        .collect();
+
+    (!ports.is_empty()).then_some(ports)
+}
+
+///
+/// The ports reported by the cloud provider
+/// in the Service load balancer status.
+///
+fn load_balancer_port_status(
+    ports: Option<&[PortStatus]>,
+) -> Option<Vec<IngressPortStatus>> {
+    let ports = ports.filter(|ports| !ports.is_empty())?;
+
+    Some(
+        ports
+            .iter()
+            .map(|p| IngressPortStatus {
+                port: p.port,
+                protocol: p.protocol.clone(),
+                error: p.error.clone(),
+            })
+            .collect(),
+    )
+}
+
 fn sort_load_balancer_ingresses(
     mut lbi: Vec<IngressLoadBalancerIngress>,
 ) -> Vec<IngressLoadBalancerIngress> {
@@ -0,0 +1,151 @@
+use k8s_openapi::api::networking::v1::{Ingress, IngressLoadBalancerIngress};
+use kube::api::{Patch, PatchParams};
+use kube::{Api, Client};
+use log::{debug, error, info};
+use serde_json::json;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::watch;
+
+///
+/// IngressStatus publishes the addresses of the controller
+/// Service in the status of the Ingresses it handles.
+///
+/// The addresses are read from the Service watcher through
+/// a watch channel, the Ingresses are patched by the leader only.
+///
+pub struct IngressStatus {
+    client: Client,
+    leader_status: Arc<AtomicBool>,
+    addresses: watch::Receiver<Vec<IngressLoadBalancerIngress>>,
+}
+
+impl IngressStatus {
+    pub fn new(
+        client: Client,
+        leader_status: Arc<AtomicBool>,
+        addresses: watch::Receiver<Vec<IngressLoadBalancerIngress>>,
+    ) -> Self {
+        IngressStatus {
+            client,
+            leader_status,
+            addresses,
+        }
+    }
+
+    ///
+    /// Wait for the addresses of the controller Service to change,
+    /// fails once the Service watcher is gone.
+    ///
+    pub async fn changed(&mut self) -> Result<(), watch::error::RecvError> {
+        self.addresses.changed().await
+    }
+
+    ///
+    /// Patch the status of the provided Ingress with the
+    /// current addresses, unless it already advertises them.
+    ///
+    /// Nothing is patched until the Service watcher publishes
+    /// the addresses, the channel starts empty.
+    ///
+    /// Returns the patched Ingress on success.
+    ///
+    pub async fn update(&self, ingress: &Ingress) -> Option<Ingress> {
+        let addresses = self.addresses.borrow().clone();
+        if !needs_advertising(ingress, &addresses) {
+            return None;
+        }
+
+        self.patch(ingress, &addresses).await
+    }
+
+    ///
+    /// Clear the status of an Ingress which is no longer served,
+    /// provided it advertises the current addresses, so that the
+    /// status set by another controller is left alone.
+    ///
+    /// Returns the patched Ingress on success.
+    ///
+    pub async fn clear(&self, ingress: &Ingress) -> Option<Ingress> {
+        let addresses = self.addresses.borrow().clone();
+        if !advertises(ingress, &addresses) {
+            return None;
+        }
+
+        self.patch(ingress, &[]).await
+    }
+
+    async fn patch(
+        &self,
+        ingress: &Ingress,
+        addresses: &[IngressLoadBalancerIngress],
+    ) -> Option<Ingress> {
+        if !self.leader_status.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        if !needs_status_update(ingress, addresses) {
+            return None;
+        }
+
+        let name = ingress.metadata.name.as_deref().unwrap_or_default();
+        let namespace = ingress.metadata.namespace.as_deref().unwrap_or("default");
+
+        let patch = json!({
+            "status": {
+                "loadBalancer": {
+                    "ingress": addresses
+                }
+            }
+        });
+
+        debug!("Applying ingress status patch {patch}");
+
+        let patch_params = PatchParams::apply("update-status");
+        let ingress_api = Api::<Ingress>::namespaced(self.client.clone(), namespace);
+
+        match ingress_api
+            .patch_status(name, &patch_params, &Patch::Merge(&patch))
+            .await
+        {
+            Ok(patched) => {
+                info!("Patched status of ingress [{namespace}/{name}]");
+                Some(patched)
+            }
+            Err(err) => {
+                error!("Failed to patch status of ingress [{namespace}/{name}]: {err:?}");
+                None
+            }
+        }
+    }
+}
+
+///
+/// Whether the addresses advertised in the status
+/// of the Ingress differ from the provided ones.
+///
+pub fn needs_status_update(ingress: &Ingress, addresses: &[IngressLoadBalancerIngress]) -> bool {
+    let current = ingress
+        .status
+        .as_ref()
+        .and_then(|status| status.load_balancer.as_ref())
+        .and_then(|load_balancer| load_balancer.ingress.as_deref())
+        .unwrap_or_default();
+
+    current != addresses
+}
+
+///
+/// Whether the Ingress advertises the provided, non empty, addresses.
+///
+pub fn advertises(ingress: &Ingress, addresses: &[IngressLoadBalancerIngress]) -> bool {
+    !addresses.is_empty() && !needs_status_update(ingress, addresses)
+}
+
+///
+/// Whether the Ingress must advertise the provided addresses, an
+/// empty list of addresses is never advertised.
+///
+pub fn needs_advertising(ingress: &Ingress, addresses: &[IngressLoadBalancerIngress]) -> bool {
+    !addresses.is_empty() && needs_status_update(ingress, addresses)
+}
@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod test {
+    use crate::status::{advertises, needs_advertising, needs_status_update};
+    use k8s_openapi::api::networking::v1::{
+        Ingress, IngressLoadBalancerIngress, IngressLoadBalancerStatus, IngressPortStatus,
+        IngressStatus,
+    };
+
+    fn address(ip: &str, port: i32) -> IngressLoadBalancerIngress {
+        IngressLoadBalancerIngress {
+            ip: Some(ip.to_string()),
+            hostname: None,
+            ports: Some(vec![IngressPortStatus {
+                port,
+                protocol: String::from("TCP"),
+                error: None,
+            }]),
+        }
+    }
+
+    fn ingress_with_status(addresses: Vec<IngressLoadBalancerIngress>) -> Ingress {
+        Ingress {
+            status: Some(IngressStatus {
+                load_balancer: Some(IngressLoadBalancerStatus {
+                    ingress: Some(addresses),
+                }),
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_needs_status_update() {
+        let addresses = vec![address("10.0.0.1", 80)];
+
+        assert!(!needs_status_update(&Ingress::default(), &[]));
+        assert!(needs_status_update(&Ingress::default(), &addresses));
+
+        let current = ingress_with_status(addresses.clone());
+        assert!(!needs_status_update(&current, &addresses));
+        assert!(needs_status_update(&current, &[address("10.0.0.1", 8080)]));
+        assert!(needs_status_update(&current, &[address("10.0.0.2", 80)]));
+        assert!(needs_status_update(&current, &[]));
+    }
+
+    #[test]
+    fn test_advertises() {
+        let addresses = vec![address("10.0.0.1", 80)];
+
+        assert!(advertises(
+            &ingress_with_status(addresses.clone()),
+            &addresses
+        ));
+        assert!(!advertises(&Ingress::default(), &addresses));
+        assert!(!advertises(&Ingress::default(), &[]));
+
+        // The status set by another controller is not ours to clear.
+        let other = ingress_with_status(vec![address("10.0.0.2", 80)]);
+        assert!(!advertises(&other, &addresses));
+    }
+
+    #[test]
+    fn test_needs_advertising() {
+        let addresses = vec![address("10.0.0.1", 80)];
+
+        assert!(needs_advertising(&Ingress::default(), &addresses));
+        assert!(!needs_advertising(
+            &ingress_with_status(addresses.clone()),
+            &addresses
+        ));
+
+        // The addresses are not published yet, the current
+        // status is kept rather than emptied.
+        assert!(!needs_advertising(&Ingress::default(), &[]));
+        assert!(!needs_advertising(&ingress_with_status(addresses), &[]));
+    }
+}
@@ -8,6 +8,11 @@ use tokio::process::Command;
 
 const VARNISH_LOG_BIN: &str = "varnishlog";
 
+/// The request headers carrying credentials, never logged, including
+/// the password hashes computed by the VCL.
+const REDACTED_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "x-vingress-sha1"];
+const REDACTED: &str = "[redacted]";
+
 pub async fn start(work_dir: &str) {
     let args: Vec<&str> = vec!["-n", work_dir, "-g", "request"];
 
@@ -38,13 +43,13 @@ pub fn get_regex_patterns() -> RegexPatterns {
         re_req_method: Regex::new(r"^-   ReqMethod\s+(\w+)").unwrap(),
         re_req_url: Regex::new(r"^-   ReqURL\s+(.+)").unwrap(),
         re_req_protocol: Regex::new(r"^-   ReqProtocol\s+(.+)").unwrap(),
-        re_req_header: Regex::new(r"^-   ReqHeader\s+(.+):\s+(.+)").unwrap(),
+        re_req_header: Regex::new(r"^-   ReqHeader\s+([^:]+):\s+(.+)").unwrap(),
         re_resp_status: Regex::new(r"^-   RespStatus\s+(\d+)").unwrap(),
         re_resp_reason: Regex::new(r"^-   RespReason\s+(.+)").unwrap(),
-        re_resp_header: Regex::new(r"^-   RespHeader\s+(.+):\s+(.+)").unwrap(),
+        re_resp_header: Regex::new(r"^-   RespHeader\s+([^:]+):\s+(.+)").unwrap(),
         re_beresp_status: Regex::new(r"^--  BerespStatus\s+(\d+)").unwrap(),
         re_beresp_reason: Regex::new(r"^--  BerespReason\s+(.+)").unwrap(),
-        re_beresp_header: Regex::new(r"^--  BerespHeader\s+(.+):\s+(.+)").unwrap(),
+        re_beresp_header: Regex::new(r"^--  BerespHeader\s+([^:]+):\s+(.+)").unwrap(),
     }
 }
 
@@ -104,9 +109,17 @@ pub async fn parse_log_line(line: &str, re_patterns: &RegexPatterns, state: &mut
         }
         _ if re_patterns.re_req_header.is_match(line) => {
             let caps = re_patterns.re_req_header.captures(line).unwrap();
+            let value = if REDACTED_HEADERS
+                .iter()
+                .any(|header| caps[1].eq_ignore_ascii_case(header))
+            {
+                REDACTED
+            } else {
+                &caps[2]
+            };
             state
                 .req_headers
-                .push((caps[1].to_string(), caps[2].to_string()));
+                .push((caps[1].to_string(), value.to_string()));
         }
         _ if re_patterns.re_resp_status.is_match(line) => {
             let caps = re_patterns.re_resp_status.captures(line).unwrap();
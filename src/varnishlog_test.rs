@@ -16,6 +16,8 @@ mod test {
             -   ReqHeader      Host: foo.bar.com
             -   ReqHeader      User-Agent: curl/8.10.1
             -   ReqHeader      Accept: */*
+            -   ReqHeader      Authorization: Basic YWxpY2U6c2VjcmV0
+            -   ReqHeader      Proxy-Authorization: Bearer a: b
             -   ReqHeader      X-Forwarded-For: 10.125.174.2
             -   RespProtocol   HTTP/1.1
             -   RespStatus     200
@@ -44,6 +46,9 @@ mod test {
                 ("Host".to_string(), "foo.bar.com".to_string()),
                 ("User-Agent".to_string(), "curl/8.10.1".to_string()),
                 ("Accept".to_string(), "*/*".to_string()),
+                ("Authorization".to_string(), "[redacted]".to_string()),
+                // The value holds a colon, the header name stops at the first one.
+                ("Proxy-Authorization".to_string(), "[redacted]".to_string()),
                 ("X-Forwarded-For".to_string(), "10.125.174.2".to_string()),
             ],
             resp_reason: "OK".to_string(),
@@ -1,15 +1,54 @@
-use crate::configmap::{SNIPPET_KEY, VCL_RECV_SNIPPET_KEY};
-use handlebars::{Handlebars, to_json};
+use crate::configmap::{DEFAULT_PROBE_KEY, SNIPPET_KEY, VCL_RECV_SNIPPET_KEY};
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderContext, RenderErrorReason,
+    handlebars_helper, no_escape, to_json,
+};
 use log::error;
 use log::info;
+use log::warn;
+use regex::Regex;
 use serde::Serialize;
 use serde_json::value::Map;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
 use std::{fs::File, io::Write, process::Command};
 
 const RELOAD_COMMAND: &str = "varnishreload";
 
 const TEMPLATE_KEY: &str = "vcl";
 const BACKEND_KEY: &str = "backend";
+const DEFAULT_BACKEND_KEY: &str = "default_backend";
+const SERVICE_BACKEND_KEY: &str = "service_backend";
+const FALLBACK_BACKEND_KEY: &str = "fallback_backend";
+const NORMALIZE_HOST_KEY: &str = "normalize_host";
+const CACHE_SETTINGS_KEY: &str = "cache_settings";
+const PIPE_KEY: &str = "pipe";
+const DIRECTOR_KEY: &str = "director";
+const CANARIES_KEY: &str = "canaries";
+const VARIANTS_KEY: &str = "variants";
+const REWRITES_KEY: &str = "rewrites";
+const HTTPS_HOSTS_KEY: &str = "https_hosts";
+const WWW_REDIRECTS_KEY: &str = "www_redirects";
+const REDIRECTS_KEY: &str = "redirects";
+const AUTH_KEY: &str = "auth";
+const DEFAULT_AUTH_KEY: &str = "default_auth";
+const DIGEST_KEY: &str = "digest";
+
+const FALLBACK_BACKEND_NAME: &str = "default";
+
+const PATH_REGEX_HELPER: &str = "path_regex";
+const HOST_REGEX_HELPER: &str = "host_regex";
+const VCL_STRING_HELPER: &str = "vcl_string";
+const COOKIE_REGEX_HELPER: &str = "cookie_regex";
+const REWRITE_REGEX_HELPER: &str = "rewrite_regex";
+const REWRITE_TARGET_HELPER: &str = "rewrite_target";
+
+handlebars_helper!(path_regex_helper: |path: str, path_type: str| path_regex(path, path_type));
+handlebars_helper!(host_regex_helper: |host: str| host_regex(host));
+handlebars_helper!(cookie_regex_helper: |name: str, value: str| cookie_regex(name, value));
+handlebars_helper!(rewrite_regex_helper: |path: str, path_type: str| rewrite_regex(path, path_type));
+handlebars_helper!(rewrite_target_helper: |target: str, path_type: str| rewrite_target(target, path_type));
 
 #[derive(Debug, PartialEq)]
 pub struct UpdateError(String);
@@ -21,30 +60,39 @@ impl std::fmt::Display for UpdateError {
 }
 
 ///
-/// Backend is a type that translates an Ingress backend
-/// into a Varnish backend.
+/// Backend is a type that translates an Ingress rule
+/// into a Varnish route, pointing at a Varnish backend.
 ///
-/// E.g. an Ingress with 3 backends will
-/// yield 4 Varnish backends in the vcl.
+/// E.g. an Ingress with 3 paths routed to 2 services will
+/// yield 3 routes and 2 Varnish backends in the vcl.
 ///
 /// https://kubernetes.io/docs/concepts/services-networking/ingress/#the-ingress-resource
 ///
 /// See vcl.hbs template file in this repository.
-#[derive(Debug, Serialize, Clone)]
+#[derive(Debug, Serialize, Clone, PartialEq)]
 pub struct Backend {
     /// The namespace where the Ingress
     /// object is located.
     pub namespace: String,
 
-    /// The name of the backend which
+    /// The name of the Varnish backend which
     /// will then be used as a backend
     /// hint in the vcl.
+    ///
+    /// See [service_backend_name].
     pub name: String,
 
     /// Host as defined in the Ingress
     /// rules.
     pub host: String,
 
+    /// Whether the host is a wildcard, e.g. *.foo.com,
+    /// matching exactly one DNS label.
+    ///
+    /// https://kubernetes.io/docs/concepts/services-networking/ingress/#hostname-wildcards
+    ///
+    pub wildcard_host: bool,
+
     /// Path as defined in the Ingress
     /// rules.
     ///
@@ -68,6 +116,327 @@ pub struct Backend {
     /// Kubernetes service port used
     /// as <port> in the Varnish backend definition.
     pub port: u16,
+
+    /// Header and cookie the requests must carry to match
+    /// this route, set by the Ingress annotations.
+    pub conditions: MatchConditions,
+
+    /// Cache lifetime of the objects fetched
+    /// through this route, set by the Ingress annotations.
+    pub cache: CacheSettings,
+
+    /// Either pass or pipe when the route bypasses
+    /// the cache, set by the Ingress annotations.
+    pub bypass: Option<String>,
+
+    /// The URL sent to the backend in place of the matched
+    /// path, set by the Ingress annotations.
+    ///
+    /// See [rewrite_regex] and [rewrite_target].
+    pub rewrite: Option<String>,
+
+    /// The redirect answered instead of routing the
+    /// request, set by the Ingress annotations.
+    pub redirect: Option<Redirect>,
+
+    /// The credentials required by this route,
+    /// set by the Ingress annotations.
+    pub auth: Option<BasicAuth>,
+
+    /// Health probe of the Varnish backend,
+    /// set by the Ingress annotations.
+    pub probe: Option<Probe>,
+
+    /// Connection settings of the Varnish backend,
+    /// set by the Ingress annotations.
+    pub settings: BackendSettings,
+
+    /// Ready endpoints of the Kubernetes service port,
+    /// only tracked when rendering endpoint backends.
+    pub endpoints: Vec<Endpoint>,
+
+    /// The percentage of the traffic sent to this route when
+    /// it comes from a canary Ingress, set by the annotations.
+    pub canary_weight: Option<u32>,
+
+    /// The canary route sharing the host and path of this
+    /// route, see [attach_canaries].
+    pub canary: Option<Box<Canary>>,
+}
+
+///
+/// Canary splits the traffic of a route between its backend and the
+/// backend of a canary route, through a weighted random director.
+///
+/// The variant is picked in vcl_recv and is part of the cache key,
+/// so that the cached objects of both variants are never mixed.
+///
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Canary {
+    /// The name of the random director.
+    pub director: String,
+
+    /// The name of the backend of the primary route.
+    pub primary: String,
+
+    /// The canary route.
+    pub backend: Backend,
+
+    /// The percentages of the traffic sent to
+    /// the canary and to the primary backends.
+    pub weight: u32,
+    pub primary_weight: u32,
+}
+
+///
+/// CacheSettings overrides the lifetime of the cached objects,
+/// rendered into vcl_backend_response for the matching routes.
+///
+/// The values are VCL durations, e.g. 120s or 1h.
+///
+/// https://varnish-cache.org/docs/trunk/users-guide/vcl-grace.html
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct CacheSettings {
+    pub ttl: Option<String>,
+    pub grace: Option<String>,
+    pub keep: Option<String>,
+}
+
+impl CacheSettings {
+    pub fn is_empty(&self) -> bool {
+        self.ttl.is_none() && self.grace.is_none() && self.keep.is_none()
+    }
+}
+
+///
+/// Redirect answers the requests of a route with a synthetic
+/// redirect response, rendered through vcl_synth.
+///
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Redirect {
+    /// Either 301 or 302.
+    pub code: u16,
+
+    /// The Location of the redirect, an http(s) URL or a path.
+    pub location: String,
+}
+
+///
+/// BasicAuth restricts a route to the requests whose Authorization
+/// header carries one of the credentials of a basic auth Secret,
+/// the others are answered with a 401 from vcl_synth.
+///
+#[derive(Serialize, Clone, PartialEq)]
+pub struct BasicAuth {
+    /// The name of the Secret, in the namespace of the Ingress.
+    pub secret: String,
+
+    /// The realm advertised in the WWW-Authenticate header.
+    pub realm: String,
+
+    /// The accepted Authorization header values.
+    pub credentials: Vec<String>,
+
+    /// The accepted `user:<hex SHA-1 of the password>` values,
+    /// checked with the digest vmod.
+    pub sha1: Vec<String>,
+}
+
+/// The credentials are kept out of the logs.
+impl std::fmt::Debug for BasicAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("BasicAuth")
+            .field("secret", &self.secret)
+            .field("realm", &self.realm)
+            .field("credentials", &self.credentials.len())
+            .field("sha1", &self.sha1.len())
+            .finish()
+    }
+}
+
+///
+/// WwwRedirect canonicalizes a host, redirecting the
+/// requests for its www or non-www variant to it.
+///
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct WwwRedirect {
+    pub from: String,
+    pub to: String,
+}
+
+///
+/// MatchConditions restricts a route to the requests carrying
+/// a header, a cookie or both with the given values, on top of
+/// the host and path of the Ingress rule.
+///
+/// The objects cached through such a route are kept apart
+/// from the ones of the routes sharing its host and path.
+///
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MatchConditions {
+    pub header: Option<MatchCondition>,
+    pub cookie: Option<MatchCondition>,
+}
+
+impl MatchConditions {
+    pub fn is_empty(&self) -> bool {
+        self.header.is_none() && self.cookie.is_none()
+    }
+}
+
+/// The name of a header or cookie and its exact value.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MatchCondition {
+    pub name: String,
+    pub value: String,
+}
+
+///
+/// DefaultBackend is a type that translates an Ingress
+/// spec.defaultBackend into a Varnish backend.
+///
+/// It catches the requests for the Ingress hosts which did not
+/// match any of the Ingress rules. An Ingress without any hosts
+/// yields a default backend which catches every unmatched request.
+///
+/// https://kubernetes.io/docs/concepts/services-networking/ingress/#default-backend
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DefaultBackend {
+    /// The namespace where the Ingress
+    /// object is located.
+    pub namespace: String,
+
+    /// The name of the backend which
+    /// will then be used as a backend
+    /// hint in the vcl.
+    pub name: String,
+
+    /// Hosts as defined in the Ingress rules,
+    /// empty when the Ingress has no hosts.
+    pub hosts: Vec<String>,
+
+    /// Wildcard hosts as defined in the Ingress rules.
+    pub wildcard_hosts: Vec<String>,
+
+    /// Kubernetes service name used
+    /// as <host> in the Varnish backend definition.
+    pub service: String,
+
+    /// Kubernetes service port used
+    /// as <port> in the Varnish backend definition.
+    pub port: u16,
+
+    /// Health probe of the Varnish backend,
+    /// set by the Ingress annotations.
+    pub probe: Option<Probe>,
+
+    /// Connection settings of the Varnish backend,
+    /// set by the Ingress annotations.
+    pub settings: BackendSettings,
+
+    /// Ready endpoints of the Kubernetes service port,
+    /// only tracked when rendering endpoint backends.
+    pub endpoints: Vec<Endpoint>,
+
+    /// The credentials required by the requests routed
+    /// to this backend, set by the Ingress annotations.
+    pub auth: Option<BasicAuth>,
+}
+
+///
+/// Endpoint is a ready pod behind a Kubernetes service port,
+/// rendered as its own Varnish backend in a director.
+///
+/// https://kubernetes.io/docs/concepts/services-networking/endpoint-slices/
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Endpoint {
+    /// Suffix of the Varnish backend name, derived
+    /// from the address and port.
+    pub id: String,
+    pub address: String,
+    pub port: u16,
+}
+
+impl Endpoint {
+    pub fn new(address: String, port: u16) -> Self {
+        let id = format!("{address}_{port}")
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+
+        Endpoint { id, address, port }
+    }
+}
+
+///
+/// Probe is a Varnish health probe, polling a backend so that
+/// Varnish stops sending it traffic once it is deemed sick.
+///
+/// The unset fields keep the Varnish defaults.
+///
+/// https://varnish-cache.org/docs/trunk/reference/vcl-probe.html
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct Probe {
+    /// The URL requested by the probe, / by default.
+    pub url: Option<String>,
+
+    /// VCL durations between two probes
+    /// and before a probe is failed.
+    pub interval: Option<String>,
+    pub timeout: Option<String>,
+
+    /// The number of latest probes considered, and how many
+    /// of them must succeed for the backend to be healthy.
+    pub window: Option<u32>,
+    pub threshold: Option<u32>,
+}
+
+///
+/// BackendSettings tunes the connections of Varnish to a backend,
+/// the unset fields keep the Varnish defaults.
+///
+/// https://varnish-cache.org/docs/trunk/reference/vcl-backend.html
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct BackendSettings {
+    /// VCL durations to wait for a connection, for the
+    /// first byte of the response and between two bytes.
+    pub connect_timeout: Option<String>,
+    pub first_byte_timeout: Option<String>,
+    pub between_bytes_timeout: Option<String>,
+
+    /// The maximum number of connections to the backend.
+    pub max_connections: Option<u32>,
+
+    /// The Host header sent when the request has none,
+    /// e.g. to the health probe.
+    pub host_header: Option<String>,
+}
+
+impl BackendSettings {
+    pub fn is_empty(&self) -> bool {
+        *self == BackendSettings::default()
+    }
+}
+
+///
+/// ServiceBackend is a Varnish backend definition pointing
+/// at a Kubernetes service port.
+///
+/// It is shared by all the routes and default backends
+/// targeting the same service port, Varnish refuses
+/// to load a VCL with duplicated backend definitions.
+///
+/// With endpoint backends, it is rendered as a director
+/// over one Varnish backend per ready endpoint instead.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct ServiceBackend {
+    pub name: String,
+    pub namespace: String,
+    pub service: String,
+    pub port: u16,
+    pub probe: Option<Probe>,
+    pub settings: BackendSettings,
+    pub endpoints: Vec<Endpoint>,
 }
 
 #[derive(Serialize)]
@@ -78,6 +447,33 @@ pub struct Vcl<'a> {
     pub snippet: String,
     pub vcl_recv_snippet: String,
     pub backends: Vec<Backend>,
+    pub default_backends: Vec<DefaultBackend>,
+    pub fallback_backend: Option<DefaultBackend>,
+    pub normalize_host: bool,
+
+    /// The cluster-wide probe of the backends without
+    /// a probe of their own, set from the command line.
+    pub default_probe: Option<Probe>,
+
+    /// The cluster-wide probe set in the configmap,
+    /// it takes precedence over the command line one.
+    pub configmap_probe: Option<Probe>,
+
+    /// The hosts whose plain HTTP requests are
+    /// redirected to HTTPS, from the Ingresses TLS.
+    pub https_hosts: Vec<String>,
+
+    /// The www and non-www host redirects.
+    pub www_redirects: Vec<WwwRedirect>,
+
+    /// The vmod_directors director grouping the endpoints of each
+    /// service port, e.g. round_robin. None when the backends point
+    /// at the services themselves.
+    pub director: Option<String>,
+
+    /// Whether the digest vmod is imported, it verifies
+    /// the `{SHA}` entries of the basic auth Secrets.
+    pub digest: bool,
 }
 
 impl<'a> Vcl<'a> {
@@ -87,6 +483,8 @@ impl<'a> Vcl<'a> {
         work_folder: &'a str,
         vcl_recv_snippet: String,
         snippet: String,
+        fallback_backend: Option<DefaultBackend>,
+        normalize_host: bool,
     ) -> Self {
         Vcl {
             template,
@@ -95,10 +493,255 @@ impl<'a> Vcl<'a> {
             snippet,
             vcl_recv_snippet,
             backends: vec![],
+            default_backends: vec![],
+            fallback_backend,
+            normalize_host,
+            default_probe: None,
+            configmap_probe: None,
+            https_hosts: vec![],
+            www_redirects: vec![],
+            director: None,
+            digest: false,
         }
     }
 }
 
+impl DefaultBackend {
+    pub fn new(
+        namespace: String,
+        name: String,
+        hosts: Vec<String>,
+        service: String,
+        port: u16,
+    ) -> Self {
+        let (wildcard_hosts, hosts) = hosts.into_iter().partition(|h| is_wildcard_host(h));
+
+        DefaultBackend {
+            namespace,
+            name,
+            hosts,
+            wildcard_hosts,
+            service,
+            port,
+            probe: None,
+            settings: BackendSettings::default(),
+            endpoints: vec![],
+            auth: None,
+        }
+    }
+}
+
+///
+/// The name of the Varnish backend pointing at a service port.
+///
+/// Kubernetes names never contain an underscore, so
+/// distinct service ports never share the same name.
+/// The prefix keeps it a valid VCL identifier when
+/// the namespace starts with a digit.
+///
+pub fn service_backend_name(namespace: &str, service: &str, port: u16) -> String {
+    format!("be_{namespace}_{service}_{port}")
+}
+
+///
+/// Collect the deduplicated, sorted set of Varnish backend
+/// definitions referenced by the routes and default backends.
+///
+pub fn service_backends(vcl: &Vcl) -> Vec<ServiceBackend> {
+    // The redirect routes never reach their backend, which Varnish
+    // would refuse to load if it is referenced nowhere else.
+    let primaries = vcl.backends.iter().filter(|b| b.redirect.is_none());
+    let canaries = primaries
+        .clone()
+        .filter_map(|b| b.canary.as_ref().map(|c| &c.backend));
+
+    let routes = primaries.chain(canaries).map(|b| ServiceBackend {
+        name: b.name.clone(),
+        namespace: b.namespace.clone(),
+        service: b.service.clone(),
+        port: b.port,
+        probe: b.probe.clone(),
+        settings: b.settings.clone(),
+        endpoints: b.endpoints.clone(),
+    });
+    let defaults = vcl.default_backends.iter().map(|d| ServiceBackend {
+        name: d.name.clone(),
+        namespace: d.namespace.clone(),
+        service: d.service.clone(),
+        port: d.port,
+        probe: d.probe.clone(),
+        settings: d.settings.clone(),
+        endpoints: d.endpoints.clone(),
+    });
+
+    let mut service_backends: BTreeMap<String, ServiceBackend> = BTreeMap::new();
+
+    // The routes are sorted, so the probe and settings of a backend
+    // shared by several Ingresses are always picked from the same one.
+    for candidate in routes.chain(defaults) {
+        match service_backends.entry(candidate.name.clone()) {
+            Entry::Vacant(entry) => {
+                entry.insert(candidate);
+            }
+            Entry::Occupied(mut entry) => {
+                let service_backend = entry.get_mut();
+                if service_backend.probe.is_none() {
+                    service_backend.probe = candidate.probe;
+                }
+                if service_backend.settings.is_empty() {
+                    service_backend.settings = candidate.settings;
+                }
+            }
+        }
+    }
+
+    service_backends.into_values().collect()
+}
+
+///
+/// Parse the cluster-wide fallback backend from its
+/// <namespace>/<service>:<port> notation.
+///
+/// An empty value means no fallback backend, in which
+/// case the VCL keeps `backend default none`.
+///
+pub fn parse_fallback_backend(value: &str) -> Result<Option<DefaultBackend>, String> {
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    let (namespace, rest) = value.split_once('/').ok_or(format!(
+        "Fallback backend [{value}] is missing the namespace"
+    ))?;
+    let (service, port) = rest
+        .split_once(':')
+        .ok_or(format!("Fallback backend [{value}] is missing the port"))?;
+
+    if namespace.is_empty() || service.is_empty() {
+        return Err(format!("Fallback backend [{value}] is malformed"));
+    }
+
+    let port = port
+        .parse::<u16>()
+        .map_err(|e| format!("Fallback backend [{value}] has an invalid port: {e}"))?;
+
+    Ok(Some(DefaultBackend::new(
+        namespace.to_string(),
+        FALLBACK_BACKEND_NAME.to_string(),
+        vec![],
+        service.to_string(),
+        port,
+    )))
+}
+
+///
+/// Parse the cluster-wide default probe from its
+/// url=/healthz,interval=5s,timeout=1s,window=5,threshold=3
+/// notation, all the keys being optional.
+///
+/// An empty value means no default probe.
+///
+pub fn parse_probe(value: &str) -> Result<Option<Probe>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+
+    let mut probe = Probe::default();
+
+    for field in value.split(',') {
+        let (key, val) = field
+            .split_once('=')
+            .map(|(key, val)| (key.trim(), val.trim()))
+            .ok_or(format!(
+                "Probe field [{}] is not a key=value pair",
+                field.escape_debug()
+            ))?;
+
+        let number = || {
+            val.parse::<u32>()
+                .map_err(|e| format!("Probe {key} [{}] is invalid: {e}", val.escape_debug()))
+        };
+
+        match key {
+            "url" => probe.url = Some(val.to_string()),
+            "interval" => probe.interval = Some(val.to_string()),
+            "timeout" => probe.timeout = Some(val.to_string()),
+            "window" => probe.window = Some(number()?),
+            "threshold" => probe.threshold = Some(number()?),
+            _ => return Err(format!("Unknown probe field [{}]", key.escape_debug())),
+        }
+    }
+
+    validate_probe(&probe)?;
+    Ok(Some(probe))
+}
+
+///
+/// Check that a probe can be safely rendered into the VCL,
+/// and that Varnish accepts its window and threshold.
+///
+pub fn validate_probe(probe: &Probe) -> Result<(), String> {
+    if let Some(url) = &probe.url {
+        if !url.starts_with('/') {
+            return Err(format!(
+                "Probe url [{}] must start with a '/'",
+                url.escape_debug()
+            ));
+        }
+        vcl_string(url)?;
+    }
+
+    for duration in [&probe.interval, &probe.timeout].into_iter().flatten() {
+        validate_duration(duration)?;
+    }
+
+    let window = probe.window.unwrap_or(8);
+    let threshold = probe.threshold.unwrap_or(3);
+
+    if !(1..=64).contains(&window) {
+        return Err(format!("Probe window [{window}] must be between 1 and 64"));
+    }
+
+    if threshold == 0 || threshold > window {
+        return Err(format!(
+            "Probe threshold [{threshold}] must be between 1 and the window [{window}]"
+        ));
+    }
+
+    Ok(())
+}
+
+///
+/// Check that the connection settings can be safely rendered
+/// into the VCL, the Host header being a host and optional port.
+///
+pub fn validate_backend_settings(settings: &BackendSettings) -> Result<(), String> {
+    for duration in [
+        &settings.connect_timeout,
+        &settings.first_byte_timeout,
+        &settings.between_bytes_timeout,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        validate_duration(duration)?;
+    }
+
+    if let Some(host_header) = &settings.host_header
+        && !host_header
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == ':')
+    {
+        return Err(format!(
+            "Host header [{}] is not a valid host",
+            host_header.escape_debug()
+        ));
+    }
+
+    Ok(())
+}
+
 impl Backend {
     pub fn new(
         namespace: String,
@@ -112,13 +755,451 @@ impl Backend {
         Backend {
             namespace,
             name,
+            wildcard_host: is_wildcard_host(&host),
             host,
             path,
             service,
             port,
             path_type,
+            conditions: MatchConditions::default(),
+            cache: CacheSettings::default(),
+            bypass: None,
+            rewrite: None,
+            redirect: None,
+            auth: None,
+            probe: None,
+            settings: BackendSettings::default(),
+            endpoints: vec![],
+            canary_weight: None,
+            canary: None,
+        }
+    }
+}
+
+///
+/// Attach the routes of the canary Ingresses to the routes with
+/// the same host, path and path type, removing them from the list.
+///
+/// The match conditions of both routes must be the same as well.
+/// The canary routes without such a primary route are ignored,
+/// as are the ones targeting a route which already has a canary.
+///
+pub fn attach_canaries(backends: &mut Vec<Backend>) {
+    let (canaries, mut routes): (Vec<Backend>, Vec<Backend>) = std::mem::take(backends)
+        .into_iter()
+        .partition(|b| b.canary_weight.is_some());
+
+    for canary in canaries {
+        let Some(primary) = routes.iter_mut().find(|b| {
+            b.host == canary.host
+                && b.path == canary.path
+                && b.path_type == canary.path_type
+                && b.conditions == canary.conditions
+        }) else {
+            warn!(
+                "Canary route [{}{}] of backend [{}] matches no route, ignoring it",
+                canary.host, canary.path, canary.name
+            );
+            continue;
+        };
+
+        if primary.redirect.is_some() {
+            warn!(
+                "Route [{}{}] redirects, ignoring the canary of backend [{}]",
+                canary.host, canary.path, canary.name
+            );
+            continue;
+        }
+
+        if primary.canary.is_some() {
+            warn!(
+                "Route [{}{}] already has a canary, ignoring the one of backend [{}]",
+                canary.host, canary.path, canary.name
+            );
+            continue;
+        }
+
+        let weight = canary.canary_weight.unwrap_or_default();
+
+        primary.canary = Some(Box::new(Canary {
+            director: format!("canary_{}_{}_{weight}", primary.name, canary.name),
+            primary: primary.name.clone(),
+            backend: canary,
+            weight,
+            primary_weight: 100 - weight,
+        }));
+    }
+
+    *backends = routes;
+}
+
+///
+/// Collect the www redirects of the canonical hosts, e.g. www.foo.com
+/// is redirected to foo.com and the other way around.
+///
+/// Wildcard hosts are ignored, and so are the redirects from
+/// a host which is itself served by one of the routes, either
+/// declared as is or matched by a wildcard, e.g. *.foo.com.
+///
+pub fn www_redirects(
+    canonical_hosts: &[String],
+    backends: &[Backend],
+    default_backends: &[DefaultBackend],
+) -> Vec<WwwRedirect> {
+    let is_declared = |host: &str| {
+        backends.iter().any(|b| host_matches(&b.host, host))
+            || default_backends
+                .iter()
+                .any(|d| d.hosts.iter().any(|h| host_matches(h, host)))
+    };
+
+    let mut redirects: Vec<WwwRedirect> = canonical_hosts
+        .iter()
+        .filter(|host| !host.is_empty() && !is_wildcard_host(host))
+        .map(|host| WwwRedirect {
+            from: match host.strip_prefix("www.") {
+                Some(bare) => bare.to_string(),
+                None => format!("www.{host}"),
+            },
+            to: host.clone(),
+        })
+        .filter(|redirect| !is_declared(&redirect.from))
+        .collect();
+
+    redirects.sort_by(|a, b| a.from.cmp(&b.from));
+    redirects.dedup_by(|a, b| a.from == b.from);
+    redirects
+}
+
+///
+/// Sort the backends by the precedence in which they
+/// must be matched in vcl_recv, the first match wins.
+///
+/// As per the Ingress spec:
+///
+/// - exact hosts go before wildcard hosts
+/// - host specific rules go before the host-less ones
+/// - Exact paths go before Prefix paths
+/// - longer paths go before the shorter ones
+///
+/// Among the routes sharing a host and path, the ones with match
+/// conditions go first, the unconditional one catching the rest.
+///
+/// The remaining ties are broken by the path, conditions and backend
+/// name, so that the same set of backends always yields
+/// the same VCL.
+///
+/// https://kubernetes.io/docs/concepts/services-networking/ingress/#multiple-matches
+///
+pub fn sort_backends(backends: &mut [Backend]) {
+    backends.sort_by(|a, b| {
+        precedence_key(a)
+            .cmp(&precedence_key(b))
+            .then_with(|| a.path.cmp(&b.path))
+            .then_with(|| a.conditions.cmp(&b.conditions))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+}
+
+fn precedence_key(b: &Backend) -> (bool, bool, &str, u8, Reverse<usize>, u8, bool) {
+    let (exact, path_type) = match b.path_type.as_str() {
+        "Exact" => (0, 0),
+        "Prefix" => (1, 1),
+        _ => (1, 2),
+    };
+
+    (
+        b.host.is_empty(),
+        b.wildcard_host,
+        b.host.as_str(),
+        exact,
+        Reverse(b.path.len()),
+        path_type,
+        b.conditions.is_empty(),
+    )
+}
+
+///
+/// Translate an Ingress path into the regex matched
+/// against req.url in vcl_recv.
+///
+/// The query string is never part of the match. Prefix
+/// paths are matched element-wise, split by `/`, so that
+/// `/foo` matches `/foo` and `/foo/bar` but not `/foobar`,
+/// a trailing slash in the Ingress path is ignored.
+///
+/// Exact and Prefix paths are quoted, so regex metacharacters
+/// are matched literally. ImplementationSpecific paths are used
+/// as regexes as they are.
+///
+/// https://kubernetes.io/docs/concepts/services-networking/ingress/#examples
+///
+pub fn path_regex(path: &str, path_type: &str) -> String {
+    match path_type {
+        "Exact" => format!("^{}(\\?.*)?$", regex::escape(path)),
+        "Prefix" => match path.trim_end_matches('/') {
+            "" => "^/".to_string(),
+            prefix => format!("^{}(/|\\?|$)", regex::escape(prefix)),
+        },
+        _ => path.to_string(),
+    }
+}
+
+///
+/// Translate a value into a VCL string literal, quotes included.
+///
+/// VCL strings have no escape sequences, so values containing a
+/// double quote are rendered as long strings: {"..."}.
+/// Values with control characters or the `"}` sequence
+/// can not be represented at all.
+///
+/// https://varnish-cache.org/docs/trunk/reference/vcl.html#strings
+///
+pub fn vcl_string(value: &str) -> Result<String, String> {
+    if value.chars().any(char::is_control) {
+        return Err(format!(
+            "Value [{}] contains control characters",
+            value.escape_debug()
+        ));
+    }
+
+    if !value.contains('"') {
+        Ok(format!("\"{value}\""))
+    } else if !value.contains("\"}") {
+        Ok(format!("{{\"{value}\"}}"))
+    } else {
+        Err(format!(
+            "Value [{value}] can not be represented as a VCL string"
+        ))
+    }
+}
+
+///
+/// Translate an Ingress path into the regex replaced by
+/// the rewrite target in bereq.url.
+///
+/// The Exact and Prefix paths only have their path replaced,
+/// the remainder of the URL and the query string are kept.
+/// ImplementationSpecific paths are used as they are, so that
+/// the target can reference their capture groups.
+///
+pub fn rewrite_regex(path: &str, path_type: &str) -> String {
+    match path_type {
+        "Exact" => format!("^{}", regex::escape(path)),
+        "Prefix" => format!("^{}/?", regex::escape(path.trim_end_matches('/'))),
+        _ => path.to_string(),
+    }
+}
+
+///
+/// Translate a rewrite target into the substitution of a VCL
+/// regsub, the `$1` capture references becoming `\1`.
+///
+/// The target of a Prefix path always ends with a `/`, so that
+/// the remainder of the URL stays a separate path segment.
+///
+pub fn rewrite_target(target: &str, path_type: &str) -> String {
+    let re = Regex::new(r"\$([0-9])").unwrap();
+    let mut target = re.replace_all(target, r"\${1}").into_owned();
+
+    if path_type == "Prefix" && !target.ends_with('/') {
+        target.push('/');
+    }
+
+    target
+}
+
+///
+/// Translate a cookie condition into the regex matched
+/// against the Cookie header, the name and value being quoted.
+///
+pub fn cookie_regex(name: &str, value: &str) -> String {
+    format!(
+        "(^|;\\s*){}={}(;|$)",
+        regex::escape(name),
+        regex::escape(value)
+    )
+}
+
+///
+/// Check that the match conditions of a route can be safely
+/// rendered into the VCL, the header and cookie names being tokens.
+///
+pub fn validate_conditions(conditions: &MatchConditions) -> Result<(), String> {
+    if let Some(header) = &conditions.header {
+        if header.name.is_empty()
+            || !header
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        {
+            return Err(format!(
+                "Header name [{}] is not valid",
+                header.name.escape_debug()
+            ));
         }
+        vcl_string(&header.value)?;
     }
+
+    if let Some(cookie) = &conditions.cookie {
+        if cookie.name.is_empty()
+            || !cookie
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        {
+            return Err(format!(
+                "Cookie name [{}] is not valid",
+                cookie.name.escape_debug()
+            ));
+        }
+        vcl_string(&cookie_regex(&cookie.name, &cookie.value))?;
+    }
+
+    Ok(())
+}
+
+///
+/// Check that the host and path of a backend can be
+/// safely rendered into the VCL.
+///
+/// A single malformed Ingress must not break the VCL
+/// compilation for all the other Ingresses.
+///
+pub fn validate_backend(b: &Backend) -> Result<(), String> {
+    validate_host(&b.host)?;
+
+    if !b.path.starts_with('/') {
+        return Err(format!("Path [{}] must start with a '/'", b.path));
+    }
+
+    let regex = path_regex(&b.path, &b.path_type);
+
+    Regex::new(&regex).map_err(|e| format!("Path [{}] is not a valid regex: {e}", b.path))?;
+    vcl_string(&regex)?;
+
+    if let Some(target) = &b.rewrite {
+        let captures = Regex::new(&rewrite_regex(&b.path, &b.path_type))
+            .map_err(|e| format!("Path [{}] is not a valid regex: {e}", b.path))?
+            .captures_len();
+
+        let re = Regex::new(r"\$([0-9])").unwrap();
+        if let Some(group) = re
+            .captures_iter(target)
+            .filter_map(|c| c[1].parse::<usize>().ok())
+            .find(|group| *group >= captures)
+        {
+            return Err(format!(
+                "Rewrite target [{}] references the missing capture group [{group}] of path [{}]",
+                target.escape_debug(),
+                b.path
+            ));
+        }
+
+        vcl_string(&rewrite_regex(&b.path, &b.path_type))?;
+    }
+
+    Ok(())
+}
+
+///
+/// Check that an Ingress host is a DNS name
+/// which can be safely rendered into the VCL.
+///
+pub fn validate_host(host: &str) -> Result<(), String> {
+    if host
+        .strip_prefix("*.")
+        .unwrap_or(host)
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        Ok(())
+    } else {
+        Err(format!(
+            "Host [{}] is not a valid DNS name",
+            host.escape_debug()
+        ))
+    }
+}
+
+fn vcl_string_helper(
+    h: &Helper,
+    _: &Handlebars,
+    _: &Context,
+    _: &mut RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let value = h.param(0).and_then(|v| v.value().as_str()).ok_or(
+        RenderErrorReason::ParamNotFoundForIndex(VCL_STRING_HELPER, 0),
+    )?;
+
+    out.write(&vcl_string(value).map_err(RenderErrorReason::Other)?)?;
+    Ok(())
+}
+
+///
+/// Sort the default backends so that the host-less ones
+/// are rendered first and the host specific ones override them.
+///
+pub fn sort_default_backends(default_backends: &mut [DefaultBackend]) {
+    default_backends.sort_by_key(|d| {
+        (
+            !d.hosts.is_empty() || !d.wildcard_hosts.is_empty(),
+            d.name.clone(),
+        )
+    });
+}
+
+///
+/// Whether an Ingress host is a wildcard host, e.g. *.foo.com.
+///
+pub fn is_wildcard_host(host: &str) -> bool {
+    host.starts_with("*.")
+}
+
+///
+/// Whether the Ingress host, possibly a wildcard, matches the
+/// host, with the same single label semantics as [host_regex].
+///
+fn host_matches(rule_host: &str, host: &str) -> bool {
+    match rule_host.strip_prefix("*.") {
+        Some(domain) => host
+            .split_once('.')
+            .is_some_and(|(label, rest)| !label.is_empty() && rest == domain),
+        None => !rule_host.is_empty() && rule_host == host,
+    }
+}
+
+///
+/// Translate an Ingress wildcard host into the regex
+/// matched against req.http.host in vcl_recv.
+///
+/// The wildcard matches exactly one DNS label, so that
+/// *.foo.com matches bar.foo.com but neither foo.com
+/// nor baz.bar.foo.com.
+///
+pub fn host_regex(host: &str) -> String {
+    match host.strip_prefix("*.") {
+        Some(domain) => format!("^[^.]+\\.{}$", regex::escape(domain)),
+        None => format!("^{}$", regex::escape(host)),
+    }
+}
+
+///
+/// Validate a VCL duration, a number followed by one
+/// of the ms, s, m, h, d, w or y units.
+///
+pub fn validate_duration(value: &str) -> Result<(), String> {
+    let re = Regex::new(r"^[0-9]+(\.[0-9]+)?(ms|s|m|h|d|w|y)$").unwrap();
+
+    if !re.is_match(value) {
+        return Err(format!(
+            "Invalid duration [{}], expected e.g. 120s or 1h",
+            value.escape_debug()
+        ));
+    }
+
+    Ok(())
 }
 
 ///
@@ -126,6 +1207,25 @@ impl Backend {
 /// list of Backend objects and VCL snippet.
 ///
 pub fn update(vcl: &Vcl) -> Result<(), UpdateError> {
+    let rendered_content = render(vcl)?;
+
+    // Write the rendered content to the specified file
+    File::create(vcl.file)
+        .and_then(|mut file| file.write_all(rendered_content.as_bytes()))
+        .map_err(|e| {
+            error!("Failed to write to VCL file [{}]: {}", vcl.file, e);
+            UpdateError(format!("VCL file write error: {e}"))
+        })?;
+
+    info!("VCL file [{}] has been successfully updated", vcl.file);
+    Ok(())
+}
+
+///
+/// Render the VCL template with the provided
+/// list of Backend objects and VCL snippet.
+///
+pub fn render(vcl: &Vcl) -> Result<String, UpdateError> {
     let mut handlebars = Handlebars::new();
 
     // Register the template file with Handlebars
@@ -136,9 +1236,102 @@ pub fn update(vcl: &Vcl) -> Result<(), UpdateError> {
             UpdateError(e.to_string())
         })?;
 
+    // The output is VCL, not HTML. Values are rendered
+    // through the vcl_string and regex helpers instead.
+    handlebars.register_escape_fn(no_escape);
+
+    handlebars.register_helper(PATH_REGEX_HELPER, Box::new(path_regex_helper));
+    handlebars.register_helper(HOST_REGEX_HELPER, Box::new(host_regex_helper));
+    handlebars.register_helper(VCL_STRING_HELPER, Box::new(vcl_string_helper));
+    handlebars.register_helper(COOKIE_REGEX_HELPER, Box::new(cookie_regex_helper));
+    handlebars.register_helper(REWRITE_REGEX_HELPER, Box::new(rewrite_regex_helper));
+    handlebars.register_helper(REWRITE_TARGET_HELPER, Box::new(rewrite_target_helper));
+
     // Prepare data for template rendering
     let mut template_data = Map::new();
     template_data.insert(BACKEND_KEY.to_string(), to_json(&vcl.backends));
+    let mut service_backends = service_backends(vcl);
+
+    // Varnish refuses to load a VCL with unreferenced probes, e.g. the
+    // probe of a director without endpoints or an unused default probe.
+    if vcl.director.is_some() {
+        for s in service_backends
+            .iter_mut()
+            .filter(|s| s.endpoints.is_empty())
+        {
+            s.probe = None;
+        }
+    }
+    let default_probe = vcl
+        .configmap_probe
+        .as_ref()
+        .or(vcl.default_probe.as_ref())
+        .filter(|_| {
+            vcl.fallback_backend.is_some()
+                || service_backends.iter().any(|s| {
+                    s.probe.is_none() && (vcl.director.is_none() || !s.endpoints.is_empty())
+                })
+        });
+
+    template_data.insert(SERVICE_BACKEND_KEY.to_string(), to_json(&service_backends));
+    template_data.insert(
+        DEFAULT_BACKEND_KEY.to_string(),
+        to_json(&vcl.default_backends),
+    );
+    template_data.insert(
+        FALLBACK_BACKEND_KEY.to_string(),
+        to_json(&vcl.fallback_backend),
+    );
+    template_data.insert(NORMALIZE_HOST_KEY.to_string(), to_json(vcl.normalize_host));
+    template_data.insert(
+        CACHE_SETTINGS_KEY.to_string(),
+        to_json(vcl.backends.iter().any(|b| !b.cache.is_empty())),
+    );
+    template_data.insert(
+        PIPE_KEY.to_string(),
+        to_json(
+            vcl.backends
+                .iter()
+                .any(|b| b.bypass.as_deref() == Some("pipe")),
+        ),
+    );
+    template_data.insert(DEFAULT_PROBE_KEY.to_string(), to_json(default_probe));
+    template_data.insert(DIRECTOR_KEY.to_string(), to_json(&vcl.director));
+
+    // Routes sharing the same canary split share its director.
+    let canaries: BTreeMap<&str, &Canary> = vcl
+        .backends
+        .iter()
+        .filter_map(|b| b.canary.as_deref())
+        .map(|c| (c.director.as_str(), c))
+        .collect();
+    let variants = !canaries.is_empty() || vcl.backends.iter().any(|b| !b.conditions.is_empty());
+    template_data.insert(
+        CANARIES_KEY.to_string(),
+        to_json(canaries.into_values().collect::<Vec<_>>()),
+    );
+    template_data.insert(VARIANTS_KEY.to_string(), to_json(variants));
+    template_data.insert(
+        REWRITES_KEY.to_string(),
+        to_json(vcl.backends.iter().any(|b| b.rewrite.is_some())),
+    );
+    template_data.insert(HTTPS_HOSTS_KEY.to_string(), to_json(&vcl.https_hosts));
+    template_data.insert(WWW_REDIRECTS_KEY.to_string(), to_json(&vcl.www_redirects));
+    template_data.insert(
+        REDIRECTS_KEY.to_string(),
+        to_json(
+            !vcl.https_hosts.is_empty()
+                || !vcl.www_redirects.is_empty()
+                || vcl.backends.iter().any(|b| b.redirect.is_some()),
+        ),
+    );
+    let default_auth = vcl.default_backends.iter().any(|d| d.auth.is_some());
+    template_data.insert(
+        AUTH_KEY.to_string(),
+        to_json(default_auth || vcl.backends.iter().any(|b| b.auth.is_some())),
+    );
+    template_data.insert(DEFAULT_AUTH_KEY.to_string(), to_json(default_auth));
+    template_data.insert(DIGEST_KEY.to_string(), to_json(vcl.digest));
     template_data.insert(SNIPPET_KEY.to_string(), to_json(&vcl.snippet));
     template_data.insert(
         VCL_RECV_SNIPPET_KEY.to_string(),
@@ -146,23 +1339,12 @@ pub fn update(vcl: &Vcl) -> Result<(), UpdateError> {
     );
 
     // Render the template with the provided data
-    let rendered_content = handlebars
+    handlebars
         .render(TEMPLATE_KEY, &template_data)
         .map_err(|e| {
             error!("Template render error: {e}");
             UpdateError(format!("Template render error: {e}"))
-        })?;
-
-    // Write the rendered content to the specified file
-    File::create(vcl.file)
-        .and_then(|mut file| file.write_all(rendered_content.as_bytes()))
-        .map_err(|e| {
-            error!("Failed to write to VCL file [{}]: {}", vcl.file, e);
-            UpdateError(format!("VCL file write error: {e}"))
-        })?;
-
-    info!("VCL file [{}] has been successfully updated", vcl.file);
-    Ok(())
+        })
 }
 
 /// Triggers Varnish to reload its VCL configuration.
@@ -1,7 +1,14 @@
 #[cfg(test)]
 mod test {
 
-    use crate::vcl::{Backend, Vcl, update};
+    use crate::vcl::{
+        Backend, BackendSettings, BasicAuth, CacheSettings, DefaultBackend, Endpoint,
+        MatchCondition, MatchConditions, Probe, Redirect, Vcl, WwwRedirect, attach_canaries,
+        cookie_regex, host_regex, parse_fallback_backend, parse_probe, path_regex, render,
+        rewrite_regex, rewrite_target, service_backend_name, sort_backends, update,
+        validate_backend, validate_duration, vcl_string, www_redirects,
+    };
+    use regex::Regex;
     use std::{fs::File, io::Read};
 
     #[test]
@@ -12,6 +19,8 @@ mod test {
             ".",
             String::default(),
             String::default(),
+            None,
+            true,
         );
 
         let mut backends: Vec<Backend> = vec![];
@@ -63,4 +72,1018 @@ mod test {
             Err(e) => panic!("{}", e),
         }
     }
+
+    #[test]
+    fn test_vcl_default_backends() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            parse_fallback_backend("fallback/catch-all:8080").unwrap(),
+            true,
+        );
+
+        v.default_backends = vec![
+            DefaultBackend::new(
+                String::from("foo"),
+                String::from("foo-web-default-service1"),
+                vec![],
+                String::from("service1"),
+                8081,
+            ),
+            DefaultBackend::new(
+                String::from("foo"),
+                String::from("foo-api-default-service2"),
+                vec![String::from("api.foo.com")],
+                String::from("service2"),
+                8082,
+            ),
+        ];
+
+        let content = render(&v).unwrap();
+
+        assert!(!content.contains("backend default none;"));
+        assert!(content.contains(".host = \"catch-all.fallback.svc.cluster.local\";"));
+        assert!(content.contains("backend foo-web-default-service1 {"));
+        assert!(content.contains("set req.backend_hint = foo-web-default-service1;"));
+        assert!(content.contains("if (req.http.host == \"api.foo.com\") {"));
+    }
+
+    #[test]
+    fn test_parse_fallback_backend() {
+        assert!(parse_fallback_backend("").unwrap().is_none());
+        assert!(parse_fallback_backend("catch-all:8080").is_err());
+        assert!(parse_fallback_backend("fallback/catch-all").is_err());
+        assert!(parse_fallback_backend("fallback/catch-all:http").is_err());
+
+        let b = parse_fallback_backend("fallback/catch-all:8080")
+            .unwrap()
+            .unwrap();
+        assert_eq!(b.namespace, "fallback");
+        assert_eq!(b.service, "catch-all");
+        assert_eq!(b.port, 8080);
+    }
+
+    fn backend(name: &str, host: &str, path: &str, path_type: &str) -> Backend {
+        Backend::new(
+            String::from("foo"),
+            name.to_string(),
+            host.to_string(),
+            path.to_string(),
+            String::from("service"),
+            path_type.to_string(),
+            8080,
+        )
+    }
+
+    #[test]
+    fn test_vcl_path_precedence() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut backends = vec![
+            backend("any-root", "", "/", "Prefix"),
+            backend("foo-root", "foo.com", "/", "Prefix"),
+            backend("foo-api", "foo.com", "/api", "Prefix"),
+            backend("foo-api-exact", "foo.com", "/api", "Exact"),
+            backend("foo-api-v1", "foo.com", "/api/v1", "Prefix"),
+            backend("bar-root", "bar.com", "/", "Prefix"),
+        ];
+
+        sort_backends(&mut backends);
+
+        let names: Vec<&str> = backends.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "bar-root",
+                "foo-api-exact",
+                "foo-api-v1",
+                "foo-api",
+                "foo-root",
+                "any-root"
+            ]
+        );
+
+        v.backends = backends.clone();
+        let first = render(&v).unwrap();
+
+        backends.reverse();
+        sort_backends(&mut backends);
+        v.backends = backends;
+        let second = render(&v).unwrap();
+
+        assert_eq!(first, second);
+        assert_eq!(first.matches("elsif (").count(), 5);
+    }
+
+    /// The Ingress paths as (path type, path), the request url
+    /// and the expected matching backend.
+    type PathExample<'a> = (Vec<(&'a str, &'a str)>, &'a str, Option<&'a str>);
+
+    /// Mimic the vcl_recv first-match chain for a single host.
+    fn route(paths: &[(&str, &str)], url: &str) -> Option<String> {
+        let mut backends: Vec<Backend> = paths
+            .iter()
+            .map(|(path_type, path)| backend(&format!("{path_type}:{path}"), "", path, path_type))
+            .collect();
+        sort_backends(&mut backends);
+
+        backends
+            .iter()
+            .find(|b| {
+                Regex::new(&path_regex(&b.path, &b.path_type))
+                    .unwrap()
+                    .is_match(url)
+            })
+            .map(|b| b.name.clone())
+    }
+
+    #[test]
+    fn test_path_matching_spec_examples() {
+        // https://kubernetes.io/docs/concepts/services-networking/ingress/#examples
+        let examples: Vec<PathExample> = vec![
+            (vec![("Prefix", "/")], "/", Some("Prefix:/")),
+            (vec![("Prefix", "/")], "/foo/bar", Some("Prefix:/")),
+            (vec![("Exact", "/foo")], "/foo", Some("Exact:/foo")),
+            (vec![("Exact", "/foo")], "/bar", None),
+            (vec![("Exact", "/foo")], "/foo/", None),
+            (vec![("Exact", "/foo/")], "/foo", None),
+            (vec![("Prefix", "/foo")], "/foo", Some("Prefix:/foo")),
+            (vec![("Prefix", "/foo")], "/foo/", Some("Prefix:/foo")),
+            (vec![("Prefix", "/foo/")], "/foo", Some("Prefix:/foo/")),
+            (vec![("Prefix", "/foo/")], "/foo/", Some("Prefix:/foo/")),
+            (vec![("Prefix", "/aaa/bb")], "/aaa/bbb", None),
+            (
+                vec![("Prefix", "/aaa/bbb")],
+                "/aaa/bbb",
+                Some("Prefix:/aaa/bbb"),
+            ),
+            (
+                vec![("Prefix", "/aaa/bbb/")],
+                "/aaa/bbb",
+                Some("Prefix:/aaa/bbb/"),
+            ),
+            (
+                vec![("Prefix", "/aaa/bbb")],
+                "/aaa/bbb/",
+                Some("Prefix:/aaa/bbb"),
+            ),
+            (
+                vec![("Prefix", "/aaa/bbb")],
+                "/aaa/bbb/ccc",
+                Some("Prefix:/aaa/bbb"),
+            ),
+            (vec![("Prefix", "/aaa/bbb")], "/aaa/bbbxyz", None),
+            (
+                vec![("Prefix", "/"), ("Prefix", "/aaa")],
+                "/aaa/ccc",
+                Some("Prefix:/aaa"),
+            ),
+            (
+                vec![("Prefix", "/"), ("Prefix", "/aaa"), ("Prefix", "/aaa/bbb")],
+                "/aaa/bbb",
+                Some("Prefix:/aaa/bbb"),
+            ),
+            (
+                vec![("Prefix", "/"), ("Prefix", "/aaa"), ("Prefix", "/aaa/bbb")],
+                "/ccc",
+                Some("Prefix:/"),
+            ),
+            (vec![("Prefix", "/aaa")], "/ccc", None),
+            (
+                vec![("Prefix", "/foo"), ("Exact", "/foo")],
+                "/foo",
+                Some("Exact:/foo"),
+            ),
+            // The query string is not part of the path.
+            (vec![("Exact", "/foo")], "/foo?bar=1", Some("Exact:/foo")),
+            (vec![("Prefix", "/foo")], "/foo?bar=1", Some("Prefix:/foo")),
+            (vec![("Prefix", "/foo")], "/foobar?x=/foo", None),
+        ];
+
+        for (paths, url, expected) in examples {
+            assert_eq!(
+                route(&paths, url).as_deref(),
+                expected,
+                "paths {paths:?} with url [{url}]"
+            );
+        }
+    }
+
+    #[test]
+    fn test_vcl_prefix_golden() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        v.backends = vec![
+            backend("exact", "foo.com", "/foo", "Exact"),
+            backend("prefix", "foo.com", "/foo/", "Prefix"),
+            backend("root", "foo.com", "/", "Prefix"),
+        ];
+
+        let content = render(&v).unwrap();
+
+        assert!(
+            content.contains(r#"if (req.http.host == "foo.com" && req.url ~ "^/foo(\?.*)?$") {"#)
+        );
+        assert!(
+            content
+                .contains(r#"elsif (req.http.host == "foo.com" && req.url ~ "^/foo(/|\?|$)") {"#)
+        );
+        assert!(content.contains(r#"elsif (req.http.host == "foo.com" && req.url ~ "^/") {"#));
+    }
+
+    #[test]
+    fn test_vcl_string() {
+        assert_eq!(vcl_string("/foo"), Ok(String::from("\"/foo\"")));
+        assert_eq!(vcl_string("/fo\"o"), Ok(String::from("{\"/fo\"o\"}")));
+        assert!(vcl_string("/fo\"}o").is_err());
+        assert!(vcl_string("/foo\n").is_err());
+    }
+
+    #[test]
+    fn test_validate_backend() {
+        assert!(validate_backend(&backend("ok", "foo.com", "/a.b", "Prefix")).is_ok());
+        assert!(validate_backend(&backend("ok", "", "/", "Prefix")).is_ok());
+        assert!(validate_backend(&backend("ok", "foo.com", "/a(b", "Exact")).is_ok());
+        assert!(validate_backend(&backend("ok", "foo.com", "/a\"}b", "Exact")).is_ok());
+
+        assert!(validate_backend(&backend("host", "foo.com\"", "/", "Prefix")).is_err());
+        assert!(validate_backend(&backend("host", "foo.com bar", "/", "Prefix")).is_err());
+        assert!(validate_backend(&backend("path", "foo.com", "foo", "Prefix")).is_err());
+        assert!(
+            validate_backend(&backend(
+                "path",
+                "foo.com",
+                "/a\"}b",
+                "ImplementationSpecific"
+            ))
+            .is_err()
+        );
+        assert!(
+            validate_backend(&backend(
+                "path",
+                "foo.com",
+                "/a(b",
+                "ImplementationSpecific"
+            ))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_path_regex_quoting() {
+        assert_eq!(path_regex("/a.b", "Exact"), "^/a\\.b(\\?.*)?$");
+        assert_eq!(path_regex("/a+b/", "Prefix"), "^/a\\+b(/|\\?|$)");
+        assert_eq!(path_regex("/a.*", "ImplementationSpecific"), "/a.*");
+
+        let regex = Regex::new(&path_regex("/a.b", "Prefix")).unwrap();
+        assert!(regex.is_match("/a.b/c"));
+        assert!(!regex.is_match("/axb/c"));
+    }
+
+    #[test]
+    fn test_wildcard_hosts() {
+        let regex = Regex::new(&host_regex("*.foo.com")).unwrap();
+        assert!(regex.is_match("bar.foo.com"));
+        assert!(!regex.is_match("baz.bar.foo.com"));
+        assert!(!regex.is_match("foo.com"));
+        assert!(!regex.is_match("barxfoo.com"));
+
+        let mut backends = vec![
+            backend("wildcard", "*.foo.com", "/", "Prefix"),
+            backend("any", "", "/", "Prefix"),
+            backend("exact", "bar.foo.com", "/", "Prefix"),
+        ];
+        sort_backends(&mut backends);
+
+        let names: Vec<&str> = backends.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(names, vec!["exact", "wildcard", "any"]);
+
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+        v.backends = backends;
+        v.default_backends = vec![DefaultBackend::new(
+            String::from("foo"),
+            String::from("foo-web-default-service1"),
+            vec![String::from("*.foo.com")],
+            String::from("service1"),
+            8081,
+        )];
+
+        let content = render(&v).unwrap();
+        assert!(
+            content.contains(r#"elsif (req.http.host ~ "^[^.]+\.foo\.com$" && req.url ~ "^/") {"#)
+        );
+        assert!(content.contains(r#"if (req.http.host ~ "^[^.]+\.foo\.com$") {"#));
+        assert!(validate_backend(&backend("wildcard", "*.foo.com", "/", "Prefix")).is_ok());
+        assert!(validate_backend(&backend("wildcard", "foo.*.com", "/", "Prefix")).is_err());
+    }
+
+    #[test]
+    fn test_hostless_rules() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut backends = vec![
+            backend("any", "", "/", "Prefix"),
+            backend("foo", "foo.com", "/", "Prefix"),
+        ];
+        sort_backends(&mut backends);
+        v.backends = backends;
+
+        let content = render(&v).unwrap();
+
+        assert!(!content.contains(r#"req.http.host == """#));
+        assert!(content.contains(r#"if (req.http.host == "foo.com" && req.url ~ "^/") {"#));
+        assert!(content.contains(r#"elsif (req.url ~ "^/") {"#));
+    }
+
+    #[test]
+    fn test_host_normalization() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("set req.http.host = std.tolower(req.http.host);"));
+        assert!(content.contains(r#"== "" + std.port(server.ip)"#));
+
+        v.normalize_host = false;
+
+        let content = render(&v).unwrap();
+        assert!(!content.contains("std.tolower(req.http.host)"));
+    }
+
+    #[test]
+    fn test_shared_backend_definitions() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let shared = service_backend_name("foo", "service", 8080);
+
+        v.backends = vec![
+            backend(&shared, "foo.com", "/a", "Prefix"),
+            backend(&shared, "foo.com", "/b", "Prefix"),
+            backend(&shared, "bar.com", "/", "Prefix"),
+        ];
+        v.default_backends = vec![DefaultBackend::new(
+            String::from("foo"),
+            shared.clone(),
+            vec![String::from("foo.com")],
+            String::from("service"),
+            8080,
+        )];
+
+        let content = render(&v).unwrap();
+
+        assert_eq!(shared, "be_foo_service_8080");
+        assert_eq!(content.matches("backend be_foo_service_8080 {").count(), 1);
+        assert_eq!(
+            content
+                .matches("set req.backend_hint = be_foo_service_8080;")
+                .count(),
+            4
+        );
+
+        // Namespaces may start with a digit, VCL identifiers may not.
+        assert_eq!(
+            service_backend_name("1foo", "service", 80),
+            "be_1foo_service_80"
+        );
+    }
+
+    #[test]
+    fn test_cache_settings() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        v.backends = vec![backend("plain", "foo.com", "/", "Prefix")];
+        assert!(!render(&v).unwrap().contains("sub vcl_backend_response"));
+
+        let mut cached = backend("cached", "*.foo.com", "/static", "Prefix");
+        cached.cache = CacheSettings {
+            ttl: Some(String::from("1h")),
+            grace: Some(String::from("30s")),
+            keep: None,
+        };
+        v.backends.insert(0, cached);
+
+        let content = render(&v).unwrap();
+        let (_, backend_response) = content.split_once("sub vcl_backend_response {").unwrap();
+
+        assert!(backend_response.contains(
+            r#"if (bereq.http.host ~ "^[^.]+\.foo\.com$" && bereq.url ~ "^/static(/|\?|$)") {"#
+        ));
+        assert!(
+            backend_response
+                .contains(r#"elsif (bereq.http.host == "foo.com" && bereq.url ~ "^/") {"#)
+        );
+        assert!(backend_response.contains("set beresp.ttl = 1h;"));
+        assert!(backend_response.contains("set beresp.grace = 30s;"));
+        assert!(!backend_response.contains("beresp.keep"));
+
+        assert!(validate_duration("120s").is_ok());
+        assert!(validate_duration("1.5h").is_ok());
+        assert!(validate_duration("250ms").is_ok());
+        assert!(validate_duration("10").is_err());
+        assert!(validate_duration("1h; return (pass)").is_err());
+    }
+
+    #[test]
+    fn test_bypass() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut api = backend("api", "foo.com", "/api", "Prefix");
+        api.bypass = Some(String::from("pass"));
+        v.backends = vec![api, backend("web", "foo.com", "/", "Prefix")];
+
+        let content = render(&v).unwrap();
+        assert_eq!(content.matches("return (pass);").count(), 1);
+        assert!(!content.contains("sub vcl_pipe"));
+
+        v.backends[0].bypass = Some(String::from("pipe"));
+
+        let content = render(&v).unwrap();
+        assert_eq!(content.matches("return (pipe);").count(), 1);
+        assert!(content.contains("sub vcl_pipe"));
+    }
+
+    #[test]
+    fn test_probes() {
+        let probe = parse_probe("url=/healthz, interval=5s,timeout=1s,window=5,threshold=3")
+            .unwrap()
+            .unwrap();
+        assert_eq!(probe.url.as_deref(), Some("/healthz"));
+        assert_eq!(probe.interval.as_deref(), Some("5s"));
+        assert_eq!(probe.window, Some(5));
+        assert_eq!(parse_probe("").unwrap(), None);
+        assert!(parse_probe("url=healthz").is_err());
+        assert!(parse_probe("window=5,threshold=6").is_err());
+        assert!(parse_probe("window=65").is_err());
+        assert!(parse_probe("interval=5").is_err());
+        assert!(parse_probe("retries=3").is_err());
+
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut probed = backend("foo_probed_8080", "foo.com", "/a", "Prefix");
+        probed.probe = Some(Probe {
+            url: Some(String::from("/ready")),
+            ..Default::default()
+        });
+        v.backends = vec![probed, backend("foo_plain_8080", "foo.com", "/b", "Prefix")];
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("probe foo_probed_8080_probe {"));
+        assert!(content.contains(r#".url = "/ready";"#));
+        assert!(content.contains(".probe = foo_probed_8080_probe;"));
+        assert!(!content.contains("default_probe"));
+
+        v.default_probe = Some(probe);
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("probe default_probe {"));
+        assert!(content.contains(".interval = 5s;"));
+        assert!(content.contains(".threshold = 3;"));
+        assert_eq!(content.matches(".probe = default_probe;").count(), 1);
+        assert!(content.contains(".probe = foo_probed_8080_probe;"));
+
+        // The configmap probe takes precedence over the command line one.
+        v.configmap_probe = parse_probe("url=/live").unwrap();
+
+        let content = render(&v).unwrap();
+        assert!(content.contains(r#".url = "/live";"#));
+        assert!(!content.contains(".interval = 5s;"));
+    }
+
+    #[test]
+    fn test_backend_settings() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let shared = service_backend_name("foo", "service", 8080);
+
+        let mut reports = backend(&shared, "foo.com", "/reports", "Prefix");
+        reports.settings = BackendSettings {
+            first_byte_timeout: Some(String::from("300s")),
+            max_connections: Some(50),
+            host_header: Some(String::from("reports.foo.com")),
+            ..Default::default()
+        };
+        v.backends = vec![
+            reports,
+            backend(&shared, "foo.com", "/", "Prefix"),
+            backend("foo_api_8080", "foo.com", "/api", "Prefix"),
+        ];
+        sort_backends(&mut v.backends);
+
+        let content = render(&v).unwrap();
+        let (_, definition) = content.split_once("backend be_foo_service_8080 {").unwrap();
+        let (definition, _) = definition.split_once('}').unwrap();
+
+        assert!(definition.contains(".first_byte_timeout = 300s;"));
+        assert!(definition.contains(".max_connections = 50;"));
+        assert!(definition.contains(r#".host_header = "reports.foo.com";"#));
+        assert!(!definition.contains(".connect_timeout"));
+        assert_eq!(content.matches(".first_byte_timeout").count(), 1);
+    }
+
+    #[test]
+    fn test_endpoint_backends() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+        v.director = Some(String::from("round_robin"));
+        v.default_probe = parse_probe("url=/healthz").unwrap();
+
+        let mut web = backend("foo_web_80", "foo.com", "/", "Prefix");
+        web.endpoints = vec![
+            Endpoint::new(String::from("10.0.0.1"), 8080),
+            Endpoint::new(String::from("fd00::2"), 8080),
+        ];
+        let mut empty = backend("foo_empty_80", "foo.com", "/empty", "Prefix");
+        empty.probe = parse_probe("url=/ready").unwrap();
+        v.backends = vec![web, empty];
+        sort_backends(&mut v.backends);
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("backend foo_web_80_10_0_0_1_8080 {"));
+        assert!(content.contains(r#".host = "fd00::2";"#));
+        assert!(content.contains(r#".port = "8080";"#));
+        assert!(content.contains("new foo_web_80 = directors.round_robin();"));
+        assert!(content.contains("foo_web_80.add_backend(foo_web_80_fd00__2_8080);"));
+        assert!(content.contains("set req.backend_hint = foo_web_80.backend();"));
+        assert_eq!(content.matches(".probe = default_probe;").count(), 2);
+        assert!(!content.contains("svc.cluster.local"));
+
+        // A director without endpoints is kept, but not its probe
+        // which Varnish would refuse as unreferenced.
+        assert!(content.contains("new foo_empty_80 = directors.round_robin();"));
+        assert!(content.contains("set req.backend_hint = foo_empty_80.backend();"));
+        assert!(!content.contains("foo_empty_80_probe"));
+
+        v.director = Some(String::from("random"));
+        let content = render(&v).unwrap();
+        assert!(content.contains("foo_web_80.add_backend(foo_web_80_10_0_0_1_8080, 1.0);"));
+
+        // An unreferenced default probe is not rendered either.
+        v.backends.retain(|b| b.name == "foo_empty_80");
+        let content = render(&v).unwrap();
+        assert!(!content.contains("default_probe"));
+    }
+
+    #[test]
+    fn test_canary() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut canary = backend("foo_web-v2_80", "foo.com", "/", "Prefix");
+        canary.canary_weight = Some(20);
+        let mut orphan = backend("foo_web-v3_80", "foo.com", "/orphan", "Prefix");
+        orphan.canary_weight = Some(50);
+
+        v.backends = vec![
+            canary,
+            orphan,
+            backend("foo_web_80", "foo.com", "/", "Prefix"),
+            backend("foo_api_80", "foo.com", "/api", "Prefix"),
+        ];
+        sort_backends(&mut v.backends);
+        attach_canaries(&mut v.backends);
+
+        assert_eq!(v.backends.len(), 2);
+        let web = v.backends.iter().find(|b| b.name == "foo_web_80").unwrap();
+        let split = web.canary.as_ref().unwrap();
+        assert_eq!(split.backend.name, "foo_web-v2_80");
+        assert_eq!((split.weight, split.primary_weight), (20, 80));
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("backend foo_web-v2_80 {"));
+        assert!(!content.contains("foo_web-v3_80"));
+        assert!(content.contains("new canary_foo_web_80_foo_web-v2_80_20 = directors.random();"));
+        assert!(
+            content.contains("canary_foo_web_80_foo_web-v2_80_20.add_backend(foo_web_80, 80.0);")
+        );
+        assert!(
+            content
+                .contains("canary_foo_web_80_foo_web-v2_80_20.add_backend(foo_web-v2_80, 20.0);")
+        );
+        assert!(content.contains(
+            "set req.backend_hint = canary_foo_web_80_foo_web-v2_80_20.backend().resolve();"
+        ));
+        assert!(content.contains("unset req.http.X-Vingress-Variant;"));
+        assert!(content.contains(r#"set req.http.X-Vingress-Variant = "foo_web-v2_80";"#));
+        assert!(content.contains("sub vcl_hash {"));
+        assert!(content.contains("unset bereq.http.X-Vingress-Variant;"));
+
+        // With endpoint backends the split is made between the directors.
+        v.director = Some(String::from("round_robin"));
+        let content = render(&v).unwrap();
+        assert!(content.contains(
+            "canary_foo_web_80_foo_web-v2_80_20.add_backend(foo_web-v2_80.backend(), 20.0);"
+        ));
+
+        v.backends = vec![backend("foo_web_80", "foo.com", "/", "Prefix")];
+        v.director = None;
+        let content = render(&v).unwrap();
+        assert!(!content.contains("sub vcl_init"));
+        assert!(!content.contains("X-Vingress-Variant"));
+    }
+
+    #[test]
+    fn test_match_conditions() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+
+        let mut beta = backend("foo_beta_80", "foo.com", "/", "Prefix");
+        beta.conditions = MatchConditions {
+            header: Some(MatchCondition {
+                name: String::from("X-Beta"),
+                value: String::from("true"),
+            }),
+            cookie: None,
+        };
+        let mut preview = backend("foo_preview_80", "foo.com", "/", "Prefix");
+        preview.conditions = MatchConditions {
+            header: None,
+            cookie: Some(MatchCondition {
+                name: String::from("preview"),
+                value: String::from("1.2"),
+            }),
+        };
+
+        v.backends = vec![
+            backend("foo_web_80", "foo.com", "/", "Prefix"),
+            preview,
+            beta,
+            backend("foo_api_80", "foo.com", "/api", "Prefix"),
+        ];
+        sort_backends(&mut v.backends);
+
+        // Longer paths still win, the conditional routes go
+        // before the unconditional one sharing their path.
+        let names: Vec<&str> = v.backends.iter().map(|b| b.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec!["foo_api_80", "foo_preview_80", "foo_beta_80", "foo_web_80"]
+        );
+
+        let content = render(&v).unwrap();
+        assert!(content.contains(r#"&& req.http.X-Beta == "true") {"#));
+        assert!(content.contains(r#"&& req.http.cookie ~ "(^|;\s*)preview=1\.2(;|$)") {"#));
+        assert!(content.contains(r#"set req.http.X-Vingress-Variant = "foo_beta_80";"#));
+        assert!(content.contains("hash_data(req.http.X-Vingress-Variant);"));
+
+        let re = Regex::new(&cookie_regex("preview", "1.2")).unwrap();
+        assert!(re.is_match("preview=1.2"));
+        assert!(re.is_match("a=b; preview=1.2; c=d"));
+        assert!(!re.is_match("preview=1x2"));
+        assert!(!re.is_match("nopreview=1.2"));
+        assert!(!re.is_match("preview=1.23"));
+    }
+
+    /// Emulates the VCL regsub of the first match, with its \N references.
+    fn regsub(url: &str, regex: &str, sub: &str) -> String {
+        let re = Regex::new(regex).unwrap();
+        let Some(caps) = re.captures(url) else {
+            return url.to_string();
+        };
+
+        let refs = Regex::new(r"\\([0-9])").unwrap();
+        let sub = refs.replace_all(sub, |r: &regex::Captures| {
+            let group = r[1].parse::<usize>().unwrap();
+            caps.get(group).map_or("", |m| m.as_str()).to_string()
+        });
+
+        let m = caps.get(0).unwrap();
+        format!("{}{sub}{}", &url[..m.start()], &url[m.end()..])
+    }
+
+    fn rewrite(url: &str, path: &str, path_type: &str, target: &str) -> String {
+        regsub(
+            url,
+            &rewrite_regex(path, path_type),
+            &rewrite_target(target, path_type),
+        )
+    }
+
+    #[test]
+    fn test_rewrite_target() {
+        assert_eq!(rewrite("/api/v1/users", "/api/v1", "Prefix", "/"), "/users");
+        assert_eq!(
+            rewrite("/api/v1/users?a=b", "/api/v1/", "Prefix", "/"),
+            "/users?a=b"
+        );
+        assert_eq!(rewrite("/api/v1", "/api/v1", "Prefix", "/"), "/");
+        assert_eq!(
+            rewrite("/api/v1?a=b", "/api/v1", "Prefix", "/v2"),
+            "/v2/?a=b"
+        );
+        assert_eq!(
+            rewrite("/login?a=b", "/login", "Exact", "/auth/login"),
+            "/auth/login?a=b"
+        );
+        assert_eq!(
+            rewrite(
+                "/api/v1/users/42",
+                "^/api/v([0-9]+)/(.*)",
+                "ImplementationSpecific",
+                "/$2?version=$1"
+            ),
+            "/users/42?version=1"
+        );
+
+        let mut b = backend(
+            "foo_api_80",
+            "foo.com",
+            "/api/(.*)",
+            "ImplementationSpecific",
+        );
+        b.rewrite = Some(String::from("/$1"));
+        assert!(validate_backend(&b).is_ok());
+        b.rewrite = Some(String::from("/$2"));
+        assert!(validate_backend(&b).is_err());
+
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+        let mut api = backend("foo_api_80", "foo.com", "/api/v1", "Prefix");
+        api.rewrite = Some(String::from("/"));
+        api.cache.ttl = Some(String::from("5m"));
+        v.backends = vec![api, backend("foo_web_80", "foo.com", "/", "Prefix")];
+        sort_backends(&mut v.backends);
+
+        let content = render(&v).unwrap();
+        assert!(content.contains("sub vcl_backend_fetch {"));
+        assert!(content.contains("if (bereq.retries == 0) {"));
+        assert!(content.contains(r#"set bereq.url = regsub(bereq.url, "^/api/v1/?", "/");"#));
+        assert!(
+            content.contains(
+                r#"if (bereq.http.host == "foo.com" && bereq.url ~ "^/api/v1(/|\?|$)") {"#
+            )
+        );
+
+        // The cache settings of the rewritten route are matched
+        // against the public URL.
+        assert!(content.contains("set bereq.http.X-Vingress-Url = bereq.url;"));
+        let api_cache = r#"if (bereq.http.host == "foo.com" && bereq.http.X-Vingress-Url ~ "^/api/v1(/|\?|$)") {
+        set beresp.ttl = 5m;"#;
+        assert!(content.contains(api_cache));
+
+        v.backends = vec![backend("foo_web_80", "foo.com", "/", "Prefix")];
+        let content = render(&v).unwrap();
+        assert!(!content.contains("sub vcl_backend_fetch"));
+    }
+
+    #[test]
+    fn test_redirects() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+        let mut old = backend("foo_old_80", "old.foo.com", "/", "Prefix");
+        old.redirect = Some(Redirect {
+            code: 301,
+            location: String::from("https://bar.com/"),
+        });
+        v.backends = vec![old, backend("foo_web_80", "www.foo.com", "/", "Prefix")];
+        sort_backends(&mut v.backends);
+
+        // The www variant is declared by a route, it is not redirected.
+        let canonical = vec![String::from("foo.com"), String::from("*.bar.com")];
+        v.www_redirects = www_redirects(&canonical, &v.backends, &[]);
+        assert!(v.www_redirects.is_empty());
+
+        // Neither is a www variant matched by a wildcard route.
+        let wildcard = vec![backend("foo_any_80", "*.baz.com", "/", "Prefix")];
+        let canonical = vec![String::from("baz.com"), String::from("qux.com")];
+        assert_eq!(
+            www_redirects(&canonical, &wildcard, &[]),
+            vec![WwwRedirect {
+                from: String::from("www.qux.com"),
+                to: String::from("qux.com"),
+            }]
+        );
+
+        v.www_redirects = www_redirects(&[String::from("www.foo.com")], &v.backends, &[]);
+        assert_eq!(
+            v.www_redirects,
+            vec![WwwRedirect {
+                from: String::from("foo.com"),
+                to: String::from("www.foo.com"),
+            }]
+        );
+        v.https_hosts = vec![String::from("www.foo.com")];
+
+        let content = render(&v).unwrap();
+        assert!(content.contains(r#"set req.http.X-Vingress-Location = "https://bar.com/";"#));
+        assert!(content.contains("return (synth(301));"));
+        assert!(content.contains(r#"if (req.http.host == "foo.com") {"#));
+        assert!(content.contains(r#"if (req.http.host ~ "^www\.foo\.com$") {"#));
+        assert!(content.contains("return (synth(308));"));
+        assert!(content.contains("set resp.http.Location = req.http.X-Vingress-Location;"));
+
+        // The backend of a redirect route is never used.
+        assert!(!content.contains("backend foo_old_80 {"));
+        assert!(content.contains("backend foo_web_80 {"));
+
+        v.https_hosts.clear();
+        v.www_redirects.clear();
+        v.backends.retain(|b| b.redirect.is_none());
+        let content = render(&v).unwrap();
+        assert!(!content.contains("X-Vingress-Location"));
+        assert!(!content.contains("sub vcl_synth"));
+    }
+
+    #[test]
+    fn test_basic_auth() {
+        let mut v = Vcl::new(
+            "default.vcl",
+            "./template/vcl.hbs",
+            ".",
+            String::default(),
+            String::default(),
+            None,
+            true,
+        );
+        let auth = BasicAuth {
+            secret: String::from("basic-auth"),
+            realm: String::from("Admin area"),
+            credentials: vec![
+                String::from("Basic YWxpY2U6c2VjcmV0"),
+                String::from("Basic Ym9iOmh1bnRlcjI="),
+            ],
+            sha1: vec![],
+        };
+        let mut admin = backend("foo_admin_80", "foo.com", "/admin", "Prefix");
+        admin.auth = Some(auth.clone());
+        admin.bypass = Some(String::from("pass"));
+        v.backends = vec![admin, backend("foo_web_80", "foo.com", "/", "Prefix")];
+        sort_backends(&mut v.backends);
+
+        let content = render(&v).unwrap();
+        assert!(content.contains(
+            r#"if (req.http.Authorization != "Basic YWxpY2U6c2VjcmV0" && req.http.Authorization != "Basic Ym9iOmh1bnRlcjI=") {"#
+        ));
+        assert!(content.contains(r#"set req.http.X-Vingress-Realm = "Admin area";"#));
+        assert!(content.contains("return (synth(401));"));
+        assert!(content.contains(
+            r#"set resp.http.WWW-Authenticate = {"Basic realm=""} + req.http.X-Vingress-Realm + {"""};"#
+        ));
+        assert!(!content.contains("X-Vingress-Auth "));
+
+        // The credentials are checked before the bypass.
+        assert!(
+            content.find("return (synth(401));").unwrap() < content.find("return (pass);").unwrap()
+        );
+
+        // The default backend is checked once no route matched.
+        let mut default = DefaultBackend::new(
+            String::from("foo"),
+            String::from("foo_web_80"),
+            vec![],
+            String::from("web"),
+            80,
+        );
+        default.auth = Some(auth);
+        v.default_backends = vec![default];
+        let content = render(&v).unwrap();
+        assert!(content.contains(r#"set req.http.X-Vingress-Auth = "foo/basic-auth";"#));
+        assert!(content.contains(r#"if (req.http.X-Vingress-Auth == "foo/basic-auth") {"#));
+        assert!(content.contains("unset bereq.http.X-Vingress-Auth;"));
+
+        v.backends.clear();
+        v.default_backends.clear();
+        let content = render(&v).unwrap();
+        assert!(!content.contains("X-Vingress-Realm"));
+        assert!(!content.contains("sub vcl_synth"));
+        assert!(!content.contains("import digest;"));
+
+        // The {SHA} entries are checked with the digest vmod.
+        v.digest = true;
+        let mut admin = backend("foo_admin_80", "foo.com", "/admin", "Prefix");
+        admin.auth = Some(BasicAuth {
+            secret: String::from("basic-auth"),
+            realm: String::from("Admin area"),
+            credentials: vec![String::from("Basic Ym9iOmh1bnRlcjI=")],
+            sha1: vec![String::from(
+                "alice:5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8",
+            )],
+        });
+        v.backends = vec![admin];
+        let content = render(&v).unwrap();
+        assert!(content.contains("import digest;"));
+        assert!(content.contains(
+            r#"set req.http.X-Vingress-Sha1 = regsub(digest.base64_decode(regsub(req.http.Authorization, "^Basic ", "")), "(?s)^([^:]*:).*$", "\1") + digest.hash_sha1("#
+        ));
+        assert!(content.contains(
+            r#"if (req.http.Authorization != "Basic Ym9iOmh1bnRlcjI=" && req.http.X-Vingress-Sha1 != "alice:5baa61e4c9b93f3f0682250b6cf8331b7ee68fd8") {"#
+        ));
+        assert!(content.contains("unset req.http.X-Vingress-Sha1;"));
+    }
 }